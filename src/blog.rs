@@ -1,72 +1,389 @@
-use crate::fs_action::{execute_fs_actions, FsAction};
-use crate::{Post, CSS_DIR_NAME, CSS_FILE_NAME, FOOTER_FILE_NAME, HEADER_FILE_NAME};
+use crate::css;
+use crate::fs_action::{add_compress_actions, execute_fs_actions, FsAction};
+use crate::images::{original_name, webp_name, ImageFormat};
+use crate::post::slugify;
+use crate::template::{post_value, posts_value, site_value, Templates};
+use crate::{
+    Config, Post, CSS_DIR_NAME, CSS_FILE_NAME, FOOTER_FILE_NAME, HEADER_FILE_NAME, SERIES_DIR_NAME,
+    STATIC_DIR_NAME, TAGS_DIR_NAME, TEMPLATES_DIR_NAME,
+};
 
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 
 use atom_syndication as atom;
+use chrono::offset::Utc;
+use minijinja::value::Value;
 
-// // TODO we don't handle title and other metadata like tags
 // // TODO if we want to do this proper we should not put header inside main
 
+/// The stylesheet of the built-in theme, used unless the site or its theme has one.
+const BUILTIN_CSS: &str = include_str!("../style.css");
+
+/// A blog with all of its posts, ready to be generated.
 #[derive(Debug)]
 pub struct Blog {
+    pub config: Config,
     pub posts: Vec<Post>,
+    /// The stylesheet to copy into the generated site, from the site or its theme. The one of
+    /// the built-in theme is written if `None`.
     pub css_path: Option<PathBuf>,
+    /// Files to copy into the static directory of the generated site, by their path inside it.
+    pub static_files: BTreeMap<PathBuf, PathBuf>,
+    /// Markdown to include at the top of every post.
     pub header: Option<String>,
+    /// Markdown to include at the bottom of every post.
     pub footer: Option<String>,
+    /// The templates of the pages.
+    pub templates: Templates,
+    /// Whether posts marked as drafts are generated too.
+    pub drafts: bool,
+    /// Whether posts created after the time of generation are generated too.
+    pub future: bool,
+}
+
+/// A post that changed since the site was last generated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostChange {
+    /// The `source` of the post that changed.
+    pub source: PathBuf,
+    /// The assets of the post that changed, were added, or were removed.
+    pub assets: Vec<PathBuf>,
 }
 
-fn generate_html(
-    title: &str,
-    css: &str,
-    body_writer: &dyn Fn(&mut String) -> Result<()>,
-) -> Result<String> {
-    let mut html = String::new();
-    html.push_str(
+/// A page at the given alias which sends visitors (and search engines) to the current URL of
+/// a post.
+fn generate_redirect_html(config: &Config, alias: &str, post: &Post) -> String {
+    // The redirect is relative, so that it also works when the site isn't served from its URL,
+    // but search engines need the full URL.
+    let mut url = String::new();
+    crate::escape::escape_href(
+        &mut url,
+        &format!(
+            "{}{}/index.html",
+            "../".repeat(alias.split('/').count()),
+            post.path.to_string_lossy()
+        ),
+    )
+    .expect("Should not fail to escape HREF in-memory");
+    let mut canonical_url = String::new();
+    crate::escape::escape_href(
+        &mut canonical_url,
+        &config.url(&format!("{}/index.html", post.path.to_string_lossy())),
+    )
+    .expect("Should not fail to escape HREF in-memory");
+    let mut title = String::new();
+    crate::escape::escape_html(&mut title, &post.title)
+        .expect("Should not fail to escape HTML in-memory");
+    let mut language = String::new();
+    crate::escape::escape_html(&mut language, &config.language)
+        .expect("Should not fail to escape HTML in-memory");
+
+    format!(
         r#"<!DOCTYPE html>
-<html>
+<html lang="{language}">
 <head>
 <meta charset="utf-8" />
-<meta name="viewport" content="width=device-width, initial-scale=1" />
+<title>{title}</title>
+<link rel="canonical" href="{canonical_url}" />
+<meta http-equiv="refresh" content="0; url={url}" />
+</head>
+<body>
+<p>This page has moved to <a href="{url}">{title}</a>.</p>
+</body>
+</html>
 "#,
-    );
-    html.push_str(&format!("<title>{}</title>\n", title));
-    html.push_str(&format!(r#"<link rel="stylesheet" href="{}">"#, css));
-    html.push('\n');
-    html.push_str(
-        "</head>\n\
-                 <body>\n\
-                 <main>\n",
-    );
+        language = language,
+        title = title,
+        url = url,
+        canonical_url = canonical_url
+    )
+}
 
-    body_writer(&mut html).context(format!("Body of post '{}' could not be written", title))?;
+/// Make sure that the redirects of the posts won't overwrite any other part of the site.
+///
+/// Every post is checked, including those which are not published, so that their pages can't
+/// be overwritten either once they are.
+fn check_aliases(posts: &[Post]) -> Result<()> {
+    let reserved = [
+        CSS_DIR_NAME,
+        STATIC_DIR_NAME,
+        TAGS_DIR_NAME,
+        SERIES_DIR_NAME,
+        "index.html",
+        "atom.xml",
+    ];
+    let mut seen: Vec<(&str, &Post)> = Vec::new();
+    for post in posts {
+        for alias in post.aliases.iter() {
+            // Everything inside a post's directory is deleted when it's generated again.
+            let first = alias.split('/').next().unwrap_or_default();
+            if reserved.contains(&first) {
+                return Err(anyhow!(
+                    "Alias {:?} of post {:?} collides with the generated '{}'",
+                    alias,
+                    post.title,
+                    first
+                ));
+            }
+            if let Some(other) = posts.iter().find(|other| other.path == first) {
+                return Err(anyhow!(
+                    "Alias {:?} of post {:?} collides with the path of post {:?}",
+                    alias,
+                    post.title,
+                    other.title
+                ));
+            }
+            if let Some((_, other)) = seen.iter().find(|(seen, _)| seen == alias) {
+                return Err(anyhow!(
+                    "Alias {:?} of post {:?} is also an alias of post {:?}",
+                    alias,
+                    post.title,
+                    other.title
+                ));
+            }
+            seen.push((alias, post));
+        }
+    }
+    Ok(())
+}
 
-    html.push_str(
-        "</main>\n\
-                 </body>\n\
-                 </html>\n ",
+/// Add the files inside `dir` and its subdirectories to `files`, by their path relative to
+/// `base`, replacing those with the same path. Performs I/O.
+fn collect_static_files(
+    base: &Path,
+    dir: &Path,
+    files: &mut BTreeMap<PathBuf, PathBuf>,
+) -> Result<()> {
+    for child in fs::read_dir(dir).context(format!("Could not read directory '{:?}'", dir))? {
+        let path = child
+            .context(format!("Could not list contents of directory '{:?}'", dir))?
+            .path();
+        if path.is_dir() {
+            collect_static_files(base, &path, files)?;
+        } else {
+            let relative = path
+                .strip_prefix(base)
+                .expect("Static file should be inside its directory");
+            files.insert(relative.to_path_buf(), path);
+        }
+    }
+    Ok(())
+}
+
+/// A tag and the posts listed under it.
+struct Tag<'a> {
+    /// Name of the tag as written in the most recent post that uses it.
+    name: &'a str,
+    slug: String,
+    posts: Vec<&'a Post>,
+}
+
+/// Group the posts by tag, keeping the order of the posts and sorting the tags by name.
+///
+/// Tags whose names only differ in case share the same page silently, but a warning is logged
+/// for different names that end up with the same page (such as "C" and "C++").
+fn collect_tags<'a>(posts: &[&'a Post]) -> Vec<Tag<'a>> {
+    let mut tags: Vec<Tag> = Vec::new();
+    let mut seen_names: Vec<String> = Vec::new();
+    for &post in posts {
+        for name in post.tags.iter() {
+            let slug = slugify(name);
+            let lowercase = name.to_lowercase();
+            let is_new_name = !seen_names.contains(&lowercase);
+            if is_new_name {
+                seen_names.push(lowercase);
+            }
+            match tags.iter_mut().find(|tag| tag.slug == slug) {
+                Some(tag) => {
+                    if is_new_name {
+                        log::warn!(
+                            "Tags {:?} and {:?} share the page \"{}/{}\", rename one of them \
+                             to list their posts apart",
+                            tag.name,
+                            name,
+                            TAGS_DIR_NAME,
+                            slug
+                        );
+                    }
+                    if !tag.posts.iter().any(|p| std::ptr::eq(*p, post)) {
+                        tag.posts.push(post);
+                    }
+                }
+                None => tags.push(Tag {
+                    name,
+                    slug,
+                    posts: vec![post],
+                }),
+            }
+        }
+    }
+    tags.sort_by(|a, b| a.slug.cmp(&b.slug));
+    tags
+}
+
+/// A series and its parts, in reading order.
+struct Series<'a> {
+    /// Name of the series as written in the most recent post that is part of it.
+    name: &'a str,
+    slug: String,
+    posts: Vec<&'a Post>,
+}
+
+/// Group the posts by series, ordering each by part number (and then by creation date)
+/// and sorting the series by name.
+fn collect_series<'a>(posts: &[&'a Post]) -> Vec<Series<'a>> {
+    let mut series: Vec<Series> = Vec::new();
+    for &post in posts {
+        let name = match &post.series {
+            Some(name) => name,
+            None => continue,
+        };
+        let slug = slugify(name);
+        match series.iter_mut().find(|series| series.slug == slug) {
+            Some(series) => series.posts.push(post),
+            None => series.push(Series {
+                name,
+                slug,
+                posts: vec![post],
+            }),
+        }
+    }
+    for series in series.iter_mut() {
+        series.posts.sort_by(|a, b| {
+            let part = |post: &Post| post.series_part.unwrap_or(u32::MAX);
+            part(a)
+                .cmp(&part(b))
+                .then_with(|| a.created.cmp(&b.created))
+                .then_with(|| a.title.cmp(&b.title))
+        });
+    }
+    series.sort_by(|a, b| a.slug.cmp(&b.slug));
+    series
+}
+
+/// The box shown in each part of a series, saying which part it is and linking to the rest.
+fn series_box(series: &Series, post: &Post) -> String {
+    let part = series
+        .posts
+        .iter()
+        .position(|p| std::ptr::eq(*p, post))
+        .expect("Post should be part of its series")
+        + 1;
+
+    let mut html = format!(
+        "<nav class=\"series\">\n<p>Part {} of {} of the series <a href=\"../{}/",
+        part,
+        series.posts.len(),
+        SERIES_DIR_NAME
     );
+    crate::escape::escape_href(&mut html, &series.slug)
+        .expect("Should not fail to escape HREF in-memory");
+    html.push_str("/index.html\">");
+    crate::escape::escape_html(&mut html, series.name)
+        .expect("Should not fail to escape HTML in-memory");
+    html.push_str("</a></p>\n<ol>\n");
+    for &other in series.posts.iter() {
+        if std::ptr::eq(other, post) {
+            html.push_str("<li class=\"current\">");
+            html.push_str(&other.title_html);
+        } else {
+            html.push_str("<li><a href=\"../");
+            crate::escape::escape_href(&mut html, &other.path.to_string_lossy())
+                .expect("Should not fail to escape HREF in-memory");
+            html.push_str("/index.html\">");
+            html.push_str(&other.title_html);
+            html.push_str("</a>");
+        }
+        html.push_str("</li>\n");
+    }
+    html.push_str("</ol>\n</nav>\n");
+    html
+}
 
-    Ok(html)
+/// The posts before and after `post` in `posts`, if it's one of them.
+fn adjacent<'a>(posts: &[&'a Post], post: &Post) -> (Option<&'a Post>, Option<&'a Post>) {
+    match posts.iter().position(|p| std::ptr::eq(*p, post)) {
+        Some(i) => (
+            i.checked_sub(1).map(|i| posts[i]),
+            posts.get(i + 1).copied(),
+        ),
+        None => (None, None),
+    }
 }
 
-fn generate_post_html(post: &Post, header: &str, footer: &str, css: &str) -> Result<String> {
-    generate_html(&post.title, css, &|html| {
-        post.write_html(header, footer, html)
-    })
+/// The actions to write an optimized version of an image of a post in `post_dir`, along with
+/// a copy of the original if the post links to it.
+fn image_actions(config: &Config, asset: &Path, post_dir: &Path, linked: bool) -> Vec<FsAction> {
+    let name = asset
+        .file_name()
+        .expect("Asset must have file name")
+        .to_string_lossy();
+    let mut actions = vec![FsAction::OptimizeImage {
+        source: asset.to_path_buf(),
+        dest: post_dir.join(&*name),
+        max_width: config.image_max_width,
+        quality: config.image_quality,
+        webp: config.image_webp && ImageFormat::from_path(asset) == Some(ImageFormat::Png),
+    }];
+    if linked {
+        actions.push(FsAction::Copy {
+            source: asset.to_path_buf(),
+            dest: post_dir.join(original_name(&name)),
+        });
+    }
+    actions
 }
 
 impl Blog {
-    pub fn from_source_dir<P: AsRef<Path>>(root: P) -> Result<Self> {
+    /// Create a blog out of already loaded posts, without header, footer or stylesheet.
+    pub fn new(config: Config, posts: Vec<Post>) -> Self {
+        Self {
+            config,
+            posts,
+            css_path: None,
+            static_files: BTreeMap::new(),
+            header: None,
+            footer: None,
+            templates: Templates::default(),
+            drafts: false,
+            future: false,
+        }
+    }
+
+    /// Load all the posts, header, footer and stylesheet in the given content directory,
+    /// along with the theme of the configuration. The templates, stylesheet and static files
+    /// of the content directory replace those of the theme with the same name. Performs I/O.
+    pub fn from_source_dir<P: AsRef<Path>>(root: P, config: Config) -> Result<Self> {
         let mut posts = vec![];
         let mut header = None;
         let mut footer = None;
         let mut css_path = None;
+        let mut static_files = BTreeMap::new();
+        let mut templates = Templates::default();
         let root = root.as_ref();
 
+        if let Some(theme) = &config.theme {
+            if !theme.is_dir() {
+                return Err(anyhow!("Theme directory '{:?}' does not exist", theme));
+            }
+            let templates_dir = theme.join(TEMPLATES_DIR_NAME);
+            if templates_dir.is_dir() {
+                templates.add_dir(&templates_dir)?;
+            }
+            let css = theme.join(CSS_FILE_NAME);
+            if css.is_file() {
+                css_path = Some(css);
+            }
+            let static_dir = theme.join(STATIC_DIR_NAME);
+            if static_dir.is_dir() {
+                collect_static_files(&static_dir, &static_dir, &mut static_files)?;
+            }
+        }
+
         for child in
             fs::read_dir(root).context(format!("Could not read root directory '{:?}'", root))?
         {
@@ -92,6 +409,12 @@ impl Blog {
                 } else if name == CSS_FILE_NAME {
                     css_path = Some(path.clone());
                     continue;
+                } else if name == TEMPLATES_DIR_NAME && path.is_dir() {
+                    templates.add_dir(&path)?;
+                    continue;
+                } else if name == STATIC_DIR_NAME && path.is_dir() {
+                    collect_static_files(&path, &path, &mut static_files)?;
+                    continue;
                 }
             }
 
@@ -101,11 +424,11 @@ impl Blog {
             };
 
             if ty.is_dir() {
-                posts.push(Post::from_source_file(path)?);
+                posts.push(Post::from_source_file(path, &config)?);
             } else if let Some(ext) = path.extension() {
                 if let Some(ext) = ext.to_str() {
                     if ext.eq_ignore_ascii_case("md") {
-                        posts.push(Post::from_source_file(path)?);
+                        posts.push(Post::from_source_file(path, &config)?);
                     }
                 }
                 // else if it's not valid UTF-8 then it won't match "md" anyway
@@ -113,13 +436,19 @@ impl Blog {
         }
 
         Ok(Self {
+            config,
             posts,
             css_path,
+            static_files,
             header,
             footer,
+            templates,
+            drafts: false,
+            future: false,
         })
     }
 
+    /// Generate the whole site into the given output directory. Performs I/O.
     pub fn generate<P: AsRef<Path>>(&self, root: P) -> Result<()> {
         let actions = self
             .generate_actions(root)
@@ -127,15 +456,250 @@ impl Blog {
         execute_fs_actions(&actions)
     }
 
+    /// Load the post with the given source again, replacing its previous version.
+    ///
+    /// Returns the previous version of the post, so that changes in its URL or in where it is
+    /// listed can be detected.
+    pub fn reload_post(&mut self, source: &Path) -> Result<Post> {
+        let post = self
+            .posts
+            .iter_mut()
+            .find(|post| post.source == source)
+            .context(format!("There is no post with source '{:?}'", source))?;
+
+        let reloaded = Post::from_source_file(source, &self.config)?;
+        Ok(std::mem::replace(post, reloaded))
+    }
+
+    /// Whether the post is part of the generated site, which is not the case for drafts and
+    /// posts scheduled for a later date unless they were requested.
+    pub fn is_published(&self, post: &Post) -> bool {
+        (self.drafts || !post.draft) && (self.future || post.created <= Utc::now())
+    }
+
+    /// The published posts, sorted from the most recently modified to the oldest.
+    fn sorted_posts(&self) -> Vec<&Post> {
+        // Do iter and collect to work over references and thus avoid cloning.
+        let mut sorted_posts: Vec<_> = self
+            .posts
+            .iter()
+            .filter(|post| self.is_published(post))
+            .collect();
+        sorted_posts.sort_by(|b, a| {
+            a.modified
+                .partial_cmp(&b.modified)
+                .expect("Failed to compare modified dates")
+                .then_with(|| {
+                    a.created
+                        .partial_cmp(&b.created)
+                        .expect("Failed to compare created dates")
+                        .then_with(|| a.title.cmp(&b.title))
+                })
+        });
+        sorted_posts
+    }
+
+    /// The published posts which are not unlisted, from the most recently modified.
+    fn listed_posts(&self) -> Vec<&Post> {
+        self.sorted_posts()
+            .into_iter()
+            .filter(|post| !post.unlisted)
+            .collect()
+    }
+
+    /// The posts linked from the navigation of `post`, which are the previous (older) and
+    /// the next (newer) listed posts.
+    pub fn adjacent_posts(&self, post: &Post) -> (Option<&Post>, Option<&Post>) {
+        let (newer, older) = adjacent(&self.listed_posts(), post);
+        (older, newer)
+    }
+
+    /// Render the complete HTML page of one of the posts, including the header and footer.
+    pub fn render_post(&self, post: &Post) -> Result<String> {
+        let listed_posts = self.listed_posts();
+        let inline_css = self.inline_css()?;
+        self.render_post_in(
+            post,
+            &listed_posts,
+            &collect_series(&listed_posts),
+            inline_css.as_deref(),
+        )
+    }
+
+    /// The stylesheet to include in every page, minified, if the site is configured to do so.
+    fn inline_css(&self) -> Result<Option<String>> {
+        if !self.config.inline_css {
+            return Ok(None);
+        }
+        let css = match &self.css_path {
+            Some(path) => fs::read_to_string(path).context(format!(
+                "Could not read contents of stylesheet '{:?}'",
+                path
+            ))?,
+            None => BUILTIN_CSS.to_string(),
+        };
+        Ok(Some(css::minify(&css)))
+    }
+
+    /// Render a page with the given template, which can also use the site's configuration,
+    /// the relative path from the page to the root of the site, the page's title and
+    /// description, and the stylesheet to include in it (if any, as given by `inline_css`).
+    ///
+    /// If the site prunes its stylesheet, only the rules which can match the elements of the
+    /// rendered page are kept in it.
+    fn render_page(
+        &self,
+        template: &str,
+        root: &str,
+        title: &str,
+        description: Option<&str>,
+        inline_css: Option<&str>,
+        context: Value,
+    ) -> Result<String> {
+        let render = |inline_css: Option<&str>| {
+            self.templates.render(
+                template,
+                minijinja::context! {
+                    site => site_value(&self.config),
+                    root,
+                    title,
+                    description,
+                    inline_css => inline_css.map(|css| Value::from_safe_string(css.to_string())),
+                    ..context.clone()
+                },
+            )
+        };
+
+        match inline_css {
+            Some(css) if self.config.prune_css => {
+                // The elements of the page don't depend on its stylesheet, so they are found
+                // in the page rendered with an empty one first.
+                let elements = css::PageElements::from_html(&render(Some(""))?);
+                render(Some(&css::prune(css, &elements)))
+            }
+            _ => render(inline_css),
+        }
+    }
+
+    /// Render the page of a post, with links to its neighbours in `listed_posts` and to the
+    /// other parts if it's in one of `series`.
+    fn render_post_in(
+        &self,
+        post: &Post,
+        listed_posts: &[&Post],
+        series: &[Series],
+        inline_css: Option<&str>,
+    ) -> Result<String> {
+        let config = &self.config;
+        let header = self.header.as_deref().unwrap_or("");
+        let footer = self.footer.as_deref().unwrap_or("");
+        let intro_html = series
+            .iter()
+            .find(|series| series.posts.iter().any(|p| std::ptr::eq(*p, post)))
+            .map(|series| series_box(series, post))
+            .unwrap_or_default();
+        // The posts are sorted from newest to oldest.
+        let (newer, older) = adjacent(listed_posts, post);
+
+        let mut content = String::new();
+        post.write_html(config, header, footer, &intro_html, &mut content)
+            .context(format!(
+                "Body of post '{}' could not be written",
+                post.title
+            ))?;
+        let summary = post.generate_summary(config.summary_length);
+        self.render_page(
+            "post.html",
+            "../",
+            &post.title,
+            summary.as_deref(),
+            inline_css,
+            minijinja::context! {
+                post => post_value(config, post),
+                content => Value::from_safe_string(content),
+                previous => older.map(|post| post_value(config, post)),
+                next => newer.map(|post| post_value(config, post)),
+            },
+        )
+        .context(format!(
+            "Could not generate HTML for post '{}', at path {:?}",
+            post.title, post.path
+        ))
+    }
+
+    /// Plan all the actions needed to generate the whole site into the given output directory,
+    /// without performing any of them.
     pub fn generate_actions<P: AsRef<Path>>(&self, root: P) -> Result<Vec<FsAction>> {
-        let blog_root = "https://expectocode.github.io/pagong".to_string(); // TODO user-provided
-        let author_name = "expectocode"; // TODO user-provided
-        let blog_title = "pagong"; // TODO user-provided
+        self.generate_actions_for(root.as_ref(), None)
+    }
+
+    /// Generate the actions needed to update a previously generated site after some posts changed.
+    ///
+    /// Only the files of the changed posts are written, without deleting their directory first,
+    /// along with the index page and the feed, because those depend on every post.
+    pub fn generate_incremental_actions<P: AsRef<Path>>(
+        &self,
+        root: P,
+        changes: &[PostChange],
+    ) -> Result<Vec<FsAction>> {
+        self.generate_actions_for(root.as_ref(), Some(changes))
+    }
+
+    /// The entry of a post in the Atom feed, with the HTML of the post as its content but
+    /// without the rest of its page, such as the header, footer or stylesheet.
+    fn feed_entry(&self, post: &Post) -> Result<atom::Entry> {
+        let config = &self.config;
+        let mut html = String::new();
+        post.write_feed_html(config, &mut html).context(format!(
+            "Body of post '{}' could not be written",
+            post.title
+        ))?;
+        let mut escaped_html = String::with_capacity(html.len());
+        crate::escape::escape_html(&mut escaped_html, &html)
+            .expect("Escaping HTML in-memory failed");
+
+        // Additionally, we could add other extra information here
+        Ok(atom::Entry {
+            title: post.title.clone(),
+            // `id` fields on entries are required to be complete URLs.
+            id: config.url(&format!("{}/index.html", post.path.to_string_lossy())),
+            updated: post.modified,
+            published: Some(post.created),
+            summary: post.generate_summary(config.summary_length),
+            content: Some(atom::Content {
+                value: Some(escaped_html),
+                src: None,
+                content_type: Some("html".to_string()),
+            }),
+            authors: vec![atom::Person {
+                name: config.author().into(),
+                ..atom::Person::default()
+            }],
+            categories: post
+                .tags
+                .iter()
+                .map(|tag| atom::Category {
+                    term: tag.clone(),
+                    ..atom::Category::default()
+                })
+                .collect(),
+            ..atom::Entry::default()
+        })
+    }
+
+    fn generate_actions_for(
+        &self,
+        root: &Path,
+        changes: Option<&[PostChange]>,
+    ) -> Result<Vec<FsAction>> {
+        let config = &self.config;
         let mut actions = vec![];
+        let inline_css = self.inline_css()?;
+        let inline_css = inline_css.as_deref();
 
         // Copy CSS assets
-        if let Some(css_source) = &self.css_path {
-            let css_path = root.as_ref().join(CSS_DIR_NAME);
+        if changes.is_none() {
+            let css_path = root.join(CSS_DIR_NAME);
             actions.push(FsAction::DeleteDir {
                 path: css_path.clone(),
                 not_exists_ok: true,
@@ -145,26 +709,57 @@ impl Blog {
                 path: css_path.clone(),
                 exists_ok: false,
             });
-            actions.push(FsAction::Copy {
-                source: css_source.clone(),
-                dest: css_path.join(CSS_FILE_NAME),
+            actions.push(match &self.css_path {
+                Some(css_source) => FsAction::Copy {
+                    source: css_source.clone(),
+                    dest: css_path.join(CSS_FILE_NAME),
+                },
+                None => FsAction::WriteFile {
+                    path: css_path.join(CSS_FILE_NAME),
+                    content: BUILTIN_CSS.to_string(),
+                },
             });
         }
 
+        // Copy the static files of the site and its theme
+        if changes.is_none() && !self.static_files.is_empty() {
+            let static_dir = root.join(STATIC_DIR_NAME);
+            actions.push(FsAction::DeleteDir {
+                path: static_dir.clone(),
+                not_exists_ok: true,
+                recursive: true,
+            });
+            actions.push(FsAction::CreateDir {
+                path: static_dir.clone(),
+                exists_ok: false,
+            });
+            for (path, source) in self.static_files.iter() {
+                // Directories are created one level at a time.
+                let mut dir = static_dir.clone();
+                for part in path.parent().into_iter().flat_map(Path::components) {
+                    dir.push(part);
+                    actions.push(FsAction::CreateDir {
+                        path: dir.clone(),
+                        exists_ok: true,
+                    });
+                }
+                actions.push(FsAction::Copy {
+                    source: source.clone(),
+                    dest: static_dir.join(path),
+                });
+            }
+        }
+
         // Sorting the posts so that the atom feed is correctly ordered.
-        // Do iter and collect to work over references and thus avoid cloning.
-        let mut sorted_posts: Vec<_> = self.posts.iter().collect();
-        sorted_posts.sort_by(|b, a| {
-            a.modified
-                .partial_cmp(&b.modified)
-                .expect("Failed to compare modified dates")
-                .then_with(|| {
-                    a.created
-                        .partial_cmp(&b.created)
-                        .expect("Failed to compare created dates")
-                        .then_with(|| a.title.cmp(&b.title))
-                })
-        });
+        check_aliases(&self.posts)?;
+        let sorted_posts = self.sorted_posts();
+
+        let listed_posts: Vec<_> = sorted_posts
+            .iter()
+            .copied()
+            .filter(|post| !post.unlisted)
+            .collect();
+        let series = collect_series(&listed_posts);
 
         // Because the atom feed also takes HTML content, generate both the
         // HTML and the feed entries in the same place.
@@ -172,121 +767,242 @@ impl Blog {
         for &post in sorted_posts.iter() {
             // TODO override name with metadata
             let post_dir_name = &post.path;
-            let post_dir = root.as_ref().join(post_dir_name);
-            actions.push(FsAction::DeleteDir {
-                path: post_dir.clone(),
-                not_exists_ok: true,
-                recursive: true,
-            });
-            actions.push(FsAction::CreateDir {
-                path: post_dir.clone(),
-                exists_ok: false,
-            });
+            let post_dir = root.join(post_dir_name);
+            let change = changes.map(|changes| changes.iter().find(|c| c.source == post.source));
+            match change {
+                None => {
+                    actions.push(FsAction::DeleteDir {
+                        path: post_dir.clone(),
+                        not_exists_ok: true,
+                        recursive: true,
+                    });
+                    actions.push(FsAction::CreateDir {
+                        path: post_dir.clone(),
+                        exists_ok: false,
+                    });
+                }
+                Some(Some(_)) => {
+                    actions.push(FsAction::CreateDir {
+                        path: post_dir.clone(),
+                        exists_ok: true,
+                    });
+                }
+                Some(None) => {}
+            }
 
             let post_path = post_dir.join("index.html");
 
-            // TODO this looks like a bad way to handle the path to the css
-            let css = format!("../{}/{}", CSS_DIR_NAME, CSS_FILE_NAME);
-            let header = self.header.as_deref().unwrap_or("");
-            let footer = self.footer.as_deref().unwrap_or("");
-            let html = generate_post_html(post, header, footer, &css).context(format!(
-                "Could not generate HTML for post '{}', at path {:?}",
-                post.title, post.path
-            ))?;
+            let html = self.render_post_in(post, &listed_posts, &series, inline_css)?;
 
-            let mut escaped_html = String::with_capacity(html.len());
-            crate::escape::escape_html(&mut escaped_html, &html)
-                .expect("Escaping HTML in-memory failed");
-
-            entries.push(
-                // Additionally, we could add category or other extra information here
-                atom::Entry {
-                    title: post.title.clone(),
-                    // `id` fields on entries are required to be complete URLs.
-                    id: PathBuf::from(&blog_root)
-                        .join(post_dir_name)
-                        .join("index.html")
-                        .to_string_lossy()
-                        .to_string(),
-                    updated: chrono::DateTime::<chrono::FixedOffset>::from(
-                        post.modified.and_hms(0, 0, 0),
-                    ),
-                    published: Some(chrono::DateTime::<chrono::FixedOffset>::from(
-                        post.created.and_hms(0, 0, 0),
-                    )),
-                    summary: post.generate_summary(),
-                    content: Some(atom::Content {
-                        value: Some(escaped_html),
-                        src: None,
-                        content_type: Some("html".to_string()),
-                    }),
-                    authors: vec![atom::Person {
-                        name: author_name.into(),
-                        ..atom::Person::default()
-                    }],
-                    ..atom::Entry::default()
-                },
-            );
+            // Unlisted posts are still generated, but can only be found through their URL.
+            if !post.unlisted {
+                entries.push(self.feed_entry(post)?);
+            }
+
+            let changed_assets = match change {
+                None => &post.assets,
+                Some(Some(change)) => &change.assets,
+                Some(None) => continue,
+            };
 
             actions.push(FsAction::WriteFile {
                 path: post_path,
                 content: html,
             });
 
-            for asset in post.assets.iter() {
+            let linked_assets = post.linked_assets();
+            for asset in changed_assets.iter() {
                 let asset_name = asset.file_name().expect("Asset must have file name");
                 let dest_path = post_dir.join(asset_name);
-                actions.push(FsAction::Copy {
-                    source: asset.into(),
-                    dest: dest_path,
+                let optimized = config.optimize_images && ImageFormat::from_path(asset).is_some();
+                if change.is_some() && !asset.exists() {
+                    actions.push(FsAction::DeleteFile {
+                        path: dest_path,
+                        not_exists_ok: true,
+                    });
+                    if optimized {
+                        let name = asset_name.to_string_lossy();
+                        for path in [webp_name(&name), original_name(&name)] {
+                            actions.push(FsAction::DeleteFile {
+                                path: post_dir.join(path),
+                                not_exists_ok: true,
+                            });
+                        }
+                    }
+                } else if optimized {
+                    let linked = linked_assets.contains(&asset);
+                    actions.extend(image_actions(config, asset, &post_dir, linked));
+                } else {
+                    actions.push(FsAction::Copy {
+                        source: asset.into(),
+                        dest: dest_path,
+                    });
+                }
+            }
+
+            // Images that didn't change may still be linked for the first time.
+            if change.is_some() && config.optimize_images {
+                for asset in linked_assets {
+                    if ImageFormat::from_path(asset).is_some()
+                        && !changed_assets.contains(asset)
+                        && asset.exists()
+                    {
+                        let name = asset.file_name().expect("Asset must have file name");
+                        actions.push(FsAction::Copy {
+                            source: asset.clone(),
+                            dest: post_dir.join(original_name(&name.to_string_lossy())),
+                        });
+                    }
+                }
+            }
+
+            // Redirect from the previous paths of the post.
+            for alias in post.aliases.iter() {
+                let mut alias_dir = root.to_path_buf();
+                for part in alias.split('/') {
+                    alias_dir.push(part);
+                    actions.push(FsAction::CreateDir {
+                        path: alias_dir.clone(),
+                        exists_ok: true,
+                    });
+                }
+                actions.push(FsAction::WriteFile {
+                    path: alias_dir.join("index.html"),
+                    content: generate_redirect_html(config, alias, post),
                 });
             }
         }
 
         // Generate main-page listing
         actions.push(FsAction::WriteFile {
-            path: root.as_ref().join("index.html"),
-            content: generate_html(
-                blog_title,
-                &format!("{}/{}", CSS_DIR_NAME, CSS_FILE_NAME),
-                &|mut html| {
-                    html.push_str("<ul>");
-                    sorted_posts.iter().for_each(|&post| {
-                        html.push_str("<li><a href=\"");
-                        crate::escape::escape_href(&mut html, &post.path.to_string_lossy())
-                            .expect("Should not fail to escape HREF in-memory");
-                        html.push_str("/index.html\">");
-                        crate::escape::escape_html(&mut html, &post.title)
-                            .expect("Should not fail to escape HTML in-memory");
-                        html.push_str("</a></li>");
-                    });
-                    html.push_str("</ul>");
-                    Ok(())
-                },
+            path: root.join("index.html"),
+            content: self.render_page(
+                "index.html",
+                "",
+                &config.title,
+                config.description.as_deref(),
+                inline_css,
+                minijinja::context! { posts => posts_value(config, &listed_posts) },
             )?,
         });
 
+        // Generate the tag pages. They list every post, so they are always written again, and
+        // those of a previous build are deleted even if no post has tags anymore.
+        let tags = collect_tags(&listed_posts);
+        let tags_dir = root.join(TAGS_DIR_NAME);
+        actions.push(FsAction::DeleteDir {
+            path: tags_dir.clone(),
+            not_exists_ok: true,
+            recursive: true,
+        });
+        if !tags.is_empty() {
+            actions.push(FsAction::CreateDir {
+                path: tags_dir.clone(),
+                exists_ok: false,
+            });
+            actions.push(FsAction::WriteFile {
+                path: tags_dir.join("index.html"),
+                content: self.render_page(
+                    "tags.html",
+                    "../",
+                    &format!("Tags - {}", config.title),
+                    None,
+                    inline_css,
+                    minijinja::context! {
+                        tags => tags
+                            .iter()
+                            .map(|tag| minijinja::context! {
+                                name => tag.name,
+                                slug => &tag.slug,
+                                posts => posts_value(config, &tag.posts),
+                            })
+                            .collect::<Vec<_>>(),
+                    },
+                )?,
+            });
+
+            for tag in tags.iter() {
+                let tag_dir = tags_dir.join(&tag.slug);
+                actions.push(FsAction::CreateDir {
+                    path: tag_dir.clone(),
+                    exists_ok: false,
+                });
+                actions.push(FsAction::WriteFile {
+                    path: tag_dir.join("index.html"),
+                    content: self.render_page(
+                        "tag.html",
+                        "../../",
+                        &format!("{} - {}", tag.name, config.title),
+                        None,
+                        inline_css,
+                        minijinja::context! {
+                            tag => minijinja::context! { name => tag.name, slug => &tag.slug },
+                            posts => posts_value(config, &tag.posts),
+                        },
+                    )?,
+                });
+            }
+        }
+
+        // Generate the series overview pages, which are always written again like the tags.
+        let series_dir = root.join(SERIES_DIR_NAME);
+        actions.push(FsAction::DeleteDir {
+            path: series_dir.clone(),
+            not_exists_ok: true,
+            recursive: true,
+        });
+        if !series.is_empty() {
+            actions.push(FsAction::CreateDir {
+                path: series_dir.clone(),
+                exists_ok: false,
+            });
+
+            for series in series.iter() {
+                let dir = series_dir.join(&series.slug);
+                actions.push(FsAction::CreateDir {
+                    path: dir.clone(),
+                    exists_ok: false,
+                });
+                actions.push(FsAction::WriteFile {
+                    path: dir.join("index.html"),
+                    content: self.render_page(
+                        "series.html",
+                        "../../",
+                        &format!("{} - {}", series.name, config.title),
+                        None,
+                        inline_css,
+                        minijinja::context! {
+                            series => minijinja::context! {
+                                name => series.name,
+                                slug => &series.slug,
+                            },
+                            posts => posts_value(config, &series.posts),
+                        },
+                    )?,
+                });
+            }
+        }
+
         // Generate atom feed
         // Similarly, we could add author, contributor, icon, or logo information here.
         // TODO: It would be nice to automatically test validity against the Atom schema,
         // to ensure the best support by feed readers.
         actions.push(FsAction::WriteFile {
-            path: root.as_ref().join("atom.xml"),
+            path: root.join("atom.xml"),
             content: atom::Feed {
-                title: blog_title.into(),
-                id: blog_root.clone(),
-                updated: if let Some(post) = sorted_posts.get(0) {
-                    chrono::DateTime::<chrono::FixedOffset>::from(post.created.and_hms(0, 0, 0))
+                title: config.title.clone(),
+                subtitle: config.description.clone(),
+                id: config.url(""),
+                // The posts are sorted by modification date, so the first one is the latest.
+                updated: if let Some(post) = listed_posts.first() {
+                    post.modified
                 } else {
                     chrono::offset::Local::now().into()
                 },
                 entries,
                 links: vec![atom::Link {
                     rel: "self".into(),
-                    href: PathBuf::from(&blog_root)
-                        .join("atom.xml")
-                        .to_string_lossy()
-                        .into(),
+                    href: config.url("atom.xml"),
                     ..atom::Link::default()
                 }],
                 ..atom::Feed::default()
@@ -294,6 +1010,10 @@ impl Blog {
             .to_string(),
         });
 
+        if config.precompress {
+            add_compress_actions(&mut actions);
+        }
+
         Ok(actions)
     }
 }
@@ -304,6 +1024,7 @@ mod tests {
     // executor so that we can test for results rather than individual actions.
     use super::*;
     use chrono::offset::Local;
+    use chrono::TimeZone;
 
     #[test]
     fn css_file_copied() -> Result<()> {
@@ -311,16 +1032,12 @@ mod tests {
         let root = Path::new("dist");
         let gen_css_dir = root.join(CSS_DIR_NAME);
 
-        let blog = Blog {
-            posts: vec![],
-            css_path: Some(source_css_file.clone()),
-            header: None,
-            footer: None,
-        };
+        let mut blog = Blog::new(Config::default(), vec![]);
+        blog.css_path = Some(source_css_file.clone());
 
         let actions = blog.generate_actions(root)?;
 
-        assert_eq!(actions.len(), 5);
+        assert_eq!(actions.len(), 7);
         assert!(matches!(&actions[0] ,
            FsAction::DeleteDir {
             path,
@@ -351,6 +1068,22 @@ mod tests {
         ));
 
         assert!(matches!(&actions[4] ,
+           FsAction::DeleteDir {
+            path,
+            not_exists_ok: true,
+            recursive: true
+           } if path == Path::new("dist/tags")
+        ));
+
+        assert!(matches!(&actions[5] ,
+           FsAction::DeleteDir {
+            path,
+            not_exists_ok: true,
+            recursive: true
+           } if path == Path::new("dist/series")
+        ));
+
+        assert!(matches!(&actions[6] ,
             FsAction::WriteFile {
                 path,
                 content
@@ -362,24 +1095,25 @@ mod tests {
 
     #[test]
     fn standalone_file_post_generated() -> Result<()> {
-        let blog = Blog {
-            posts: vec![Post {
-                path: "test_post".into(),
+        let blog = Blog::new(
+            Config::default(),
+            vec![Post {
                 markdown: "A test post".into(),
                 title: "A test post title".into(),
-                modified: Local::today(),
-                created: Local::today(),
-                assets: vec![],
+                title_html: "A test post title".into(),
+                ..Post::for_test("test_post", Local::now().into())
             }],
-            css_path: None,
-            header: None,
-            footer: None,
-        };
+        );
 
         let actions = blog.generate_actions("dist")?;
 
-        assert_eq!(actions.len(), 5);
-        assert!(matches!(&actions[0] ,
+        // Without a stylesheet of its own, the one of the built-in theme is written.
+        assert_eq!(actions.len(), 10);
+        assert!(matches!(&actions[2],
+            FsAction::WriteFile { path, content }
+                if path == Path::new("dist/css/style.css") && content == BUILTIN_CSS
+        ));
+        assert!(matches!(&actions[3] ,
            FsAction::DeleteDir {
             path,
             not_exists_ok: true,
@@ -387,28 +1121,28 @@ mod tests {
            } if path == Path::new("dist/test_post")
         ));
 
-        assert!(matches!(&actions[1] ,
+        assert!(matches!(&actions[4] ,
           FsAction::CreateDir {
             path,
             ..
            } if path == Path::new("dist/test_post")
         ));
 
-        assert!(matches!(&actions[2] ,
+        assert!(matches!(&actions[5] ,
            FsAction::WriteFile {
             path,
             content
            } if path == Path::new("dist/test_post/index.html") && content.contains("A test post")
         ));
 
-        assert!(matches!(&actions[3] ,
+        assert!(matches!(&actions[6] ,
             FsAction::WriteFile {
                 path,
                 content
             } if path == Path::new("dist/index.html") && content.contains("<ul>")
         ));
 
-        assert!(matches!(&actions[4] ,
+        assert!(matches!(&actions[9] ,
             FsAction::WriteFile {
                 path,
                 content
@@ -416,4 +1150,596 @@ mod tests {
         ));
         Ok(())
     }
+
+    #[test]
+    fn config_used_in_index_and_feed() -> Result<()> {
+        let blog = Blog::new(
+            Config::from_toml(
+                r#"
+title = "Slow & steady"
+base_url = "https://example.com/blog/"
+author = "Someone"
+language = "es"
+description = "Posts for slow connections"
+"#,
+            )?,
+            vec![Post {
+                markdown: "A test post".into(),
+                title: "A test post title".into(),
+                title_html: "A test post title".into(),
+                ..Post::for_test("test_post", Local::now().into())
+            }],
+        );
+
+        let actions = blog.generate_actions("dist")?;
+
+        assert!(matches!(&actions[6] ,
+            FsAction::WriteFile {
+                path,
+                content
+            } if path == Path::new("dist/index.html")
+                && content.contains("<html lang=\"es\">")
+                && content.contains("<title>Slow &amp; steady</title>")
+                && content.contains("content=\"Posts for slow connections\"")
+                && content.contains("<p class=\"summary\">A test post</p>")
+        ));
+        assert!(matches!(&actions[5] ,
+            FsAction::WriteFile {
+                path,
+                content
+            } if path == Path::new("dist/test_post/index.html")
+                && content.contains("<meta name=\"description\" content=\"A test post\" />")
+        ));
+
+        assert!(matches!(&actions[9] ,
+            FsAction::WriteFile {
+                path,
+                content
+            } if path == Path::new("dist/atom.xml")
+                && content.contains("https://example.com/blog/atom.xml")
+                && content.contains("https://example.com/blog/test_post/index.html")
+                && content.contains("<name>Someone</name>")
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn theme_files_overridden_by_site() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("pagong-theme-{}", std::process::id()));
+        let theme = dir.join("theme");
+        let content = dir.join("content");
+        fs::create_dir_all(theme.join("templates"))?;
+        fs::create_dir_all(theme.join("static/fonts"))?;
+        fs::create_dir_all(content.join("templates"))?;
+        fs::create_dir_all(content.join("static"))?;
+        fs::write(theme.join("style.css"), "main {}")?;
+        fs::write(theme.join("templates/index.html"), "theme index")?;
+        fs::write(theme.join("static/logo.svg"), "theme logo")?;
+        fs::write(theme.join("static/fonts/serif.woff2"), "font")?;
+        fs::write(content.join("templates/index.html"), "site index")?;
+        fs::write(content.join("static/logo.svg"), "site logo")?;
+        fs::write(content.join("hello.md"), "# Hello")?;
+
+        let config = Config {
+            theme: Some(theme.clone()),
+            ..Config::default()
+        };
+        let blog = Blog::from_source_dir(&content, config);
+        fs::remove_dir_all(&dir)?;
+        let blog = blog?;
+
+        assert_eq!(blog.posts.len(), 1);
+        assert_eq!(blog.css_path, Some(theme.join("style.css")));
+        assert_eq!(
+            blog.static_files.keys().collect::<Vec<_>>(),
+            vec![Path::new("fonts/serif.woff2"), Path::new("logo.svg")]
+        );
+        assert_eq!(
+            blog.static_files[Path::new("logo.svg")],
+            content.join("static/logo.svg")
+        );
+
+        let actions = blog.generate_actions("dist")?;
+        assert!(actions.iter().any(|action| matches!(action,
+            FsAction::CreateDir { path, exists_ok: true } if path == Path::new("dist/static/fonts")
+        )));
+        assert!(actions.iter().any(|action| matches!(action,
+            FsAction::Copy { dest, .. } if dest == Path::new("dist/static/fonts/serif.woff2")
+        )));
+        assert!(actions.iter().any(|action| matches!(action,
+            FsAction::WriteFile { path, content }
+                if path == Path::new("dist/index.html") && content == "site index"
+        )));
+
+        let missing = Config {
+            theme: Some(dir.join("missing")),
+            ..Config::default()
+        };
+        assert!(Blog::from_source_dir(&content, missing).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn css_inlined_when_configured() -> Result<()> {
+        let config = Config {
+            inline_css: true,
+            ..Config::default()
+        };
+        let blog = Blog::new(config, vec![Post::from_markdown("hello.md", "# Hi".into())]);
+
+        let html = blog.render_post(&blog.posts[0])?;
+        assert!(html.contains("<style>body{background-color:#fffff0;margin:0}"));
+        assert!(!html.contains("<link rel=\"stylesheet\""));
+
+        // The feed only gets the content of the post.
+        let actions = blog.generate_actions("dist")?;
+        assert!(actions.iter().any(|action| matches!(action,
+            FsAction::WriteFile { path, content } if path == Path::new("dist/atom.xml")
+                && content.contains("Hi&lt;/h1&gt;")
+                && !content.contains("style")
+                && !content.contains("All posts")
+        )));
+
+        let blog = Blog::new(Config::default(), vec![]);
+        let actions = blog.generate_actions("dist")?;
+        assert!(actions.iter().any(|action| matches!(action,
+            FsAction::WriteFile { path, content } if path == Path::new("dist/index.html")
+                && content.contains("<link rel=\"stylesheet\" href=\"css/style.css\">")
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn text_files_precompressed_when_configured() -> Result<()> {
+        let config = Config {
+            precompress: true,
+            ..Config::default()
+        };
+        let blog = Blog::new(config, vec![Post::from_markdown("hello.md", "# Hi".into())]);
+        let actions = blog.generate_actions("dist")?;
+        let compressed: Vec<_> = actions
+            .iter()
+            .filter_map(|action| match action {
+                FsAction::Compress { dest, .. } => Some(dest.to_string_lossy()),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(
+            compressed,
+            [
+                "dist/css/style.css.gz",
+                "dist/css/style.css.br",
+                "dist/hello/index.html.gz",
+                "dist/hello/index.html.br",
+                "dist/index.html.gz",
+                "dist/index.html.br",
+                "dist/atom.xml.gz",
+                "dist/atom.xml.br",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn images_optimized_when_configured() -> Result<()> {
+        let config = Config {
+            optimize_images: true,
+            image_max_width: 800,
+            image_webp: true,
+            ..Config::default()
+        };
+        let mut post = Post::from_markdown(
+            "photos.md",
+            "# Photos\n\n![A cat](cat.jpg) [Full size](cat.jpg)\n\n![A chart](chart.png)".into(),
+        );
+        post.assets = vec![
+            "content/photos/cat.jpg".into(),
+            "content/photos/chart.png".into(),
+            "content/photos/notes.txt".into(),
+        ];
+        let blog = Blog::new(config, vec![post]);
+
+        let html = blog.render_post(&blog.posts[0])?;
+        assert!(html.contains("<img src=\"cat.jpg\" alt=\"A cat\" />"));
+        assert!(html.contains("<a href=\"cat.original.jpg\">Full size</a>"));
+
+        let actions = blog.generate_actions("dist")?;
+        let described: Vec<_> = actions
+            .iter()
+            .filter(|action| {
+                matches!(
+                    action,
+                    FsAction::OptimizeImage { .. } | FsAction::Copy { .. }
+                )
+            })
+            .map(FsAction::describe)
+            .collect();
+        assert_eq!(
+            described,
+            [
+                "optimize     content/photos/cat.jpg -> dist/photos/cat.jpg \
+                 (up to 800px wide, quality 80)",
+                "copy         content/photos/cat.jpg -> dist/photos/cat.original.jpg \
+                 (size unknown)",
+                "optimize     content/photos/chart.png -> dist/photos/chart.png \
+                 (up to 800px wide, quality 80, and as WebP)",
+                "copy         content/photos/notes.txt -> dist/photos/notes.txt (size unknown)",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn inlined_css_pruned_when_configured() -> Result<()> {
+        let config = Config {
+            inline_css: true,
+            prune_css: true,
+            ..Config::default()
+        };
+        let blog = Blog::new(config, vec![Post::from_markdown("hello.md", "# Hi".into())]);
+        let full = blog.inline_css()?.expect("Stylesheet should be inlined");
+        assert!(full.contains("table"));
+
+        let html = blog.render_post(&blog.posts[0])?;
+        let start = html.find("<style>").expect("Stylesheet should be inlined") + 7;
+        let css = &html[start..start + html[start..].find("</style>").unwrap()];
+        assert!(css.len() < full.len());
+        assert!(css.contains("body{background-color:#fffff0;margin:0}"));
+        assert!(!css.contains("table"));
+        Ok(())
+    }
+
+    #[test]
+    fn tag_pages_generated() -> Result<()> {
+        let post = |name: &str, tags: &[&str]| Post {
+            tags: tags.iter().map(|&tag| tag.into()).collect(),
+            ..Post::for_test(name, Local::now().into())
+        };
+        let blog = Blog::new(
+            Config::default(),
+            vec![
+                post("first", &["Rust", "Slow Web"]),
+                post("second", &["rust"]),
+                post("untagged", &[]),
+            ],
+        );
+
+        let actions = blog.generate_actions("dist")?;
+        let written = |target: &str| {
+            actions.iter().find_map(|action| match action {
+                FsAction::WriteFile { path, content } if path == Path::new(target) => {
+                    Some(content.as_str())
+                }
+                _ => None,
+            })
+        };
+
+        let index = written("dist/tags/index.html").expect("Tag index should be written");
+        assert!(index.contains("<a href=\"rust/index.html\">rust</a> (2)"));
+        assert!(index.contains("<a href=\"slow-web/index.html\">Slow Web</a> (1)"));
+        assert!(index.contains("href=\"../css/style.css\""));
+
+        let rust = written("dist/tags/rust/index.html").expect("Tag page should be written");
+        assert!(rust.contains("../../first/index.html"));
+        assert!(rust.contains("../../second/index.html"));
+        assert!(!rust.contains("untagged"));
+
+        let first = written("dist/first/index.html").expect("Post should be written");
+        assert!(first.contains("<a href=\"../tags/slow-web/index.html\">Slow Web</a>"));
+
+        let feed = written("dist/atom.xml").expect("Feed should be written");
+        assert!(feed.contains("<category term=\"Slow Web\""));
+        // The links to the tag pages are relative to the posts, not to the feed.
+        assert!(!feed.contains("tags/slow-web"));
+
+        // The tag pages of a previous build are removed once no post has tags.
+        let blog = Blog::new(Config::default(), vec![post("untagged", &[])]);
+        let actions = blog.generate_actions("dist")?;
+        assert!(actions.iter().any(|action| matches!(action,
+            FsAction::DeleteDir { path, .. } if path == Path::new("dist/tags")
+        )));
+        assert!(!actions.iter().any(|action| matches!(action,
+            FsAction::WriteFile { path, .. } if path.starts_with("dist/tags")
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn series_pages_generated() -> Result<()> {
+        let post = |name: &str, series: Option<&str>, part: Option<u32>| Post {
+            series: series.map(Into::into),
+            series_part: part,
+            ..Post::for_test(name, Local::now().into())
+        };
+        let blog = Blog::new(
+            Config::default(),
+            vec![
+                post("second", Some("Learning Rust"), Some(2)),
+                post("first", Some("learning rust"), Some(1)),
+                post("extra", Some("Learning Rust"), None),
+                post("alone", None, None),
+            ],
+        );
+
+        let actions = blog.generate_actions("dist")?;
+        let written = |target: &str| {
+            actions.iter().find_map(|action| match action {
+                FsAction::WriteFile { path, content } if path == Path::new(target) => {
+                    Some(content.as_str())
+                }
+                _ => None,
+            })
+        };
+
+        let second = written("dist/second/index.html").expect("Post should be written");
+        assert!(second.contains(
+            "<p>Part 2 of 3 of the series <a href=\"../series/learning-rust/index.html\">"
+        ));
+        assert!(second.contains("<li><a href=\"../first/index.html\">first</a></li>"));
+        assert!(second.contains("<li class=\"current\">second</li>"));
+        let first = second.find("first/index.html").unwrap();
+        let extra = second.find("extra/index.html").unwrap();
+        assert!(first < extra);
+
+        let alone = written("dist/alone/index.html").expect("Post should be written");
+        assert!(!alone.contains("class=\"series\""));
+
+        let overview =
+            written("dist/series/learning-rust/index.html").expect("Series page should be written");
+        assert!(overview.contains("A series in 3 parts."));
+        assert!(overview.contains("../../first/index.html"));
+        assert!(!overview.contains("alone"));
+
+        // The series pages of a previous build are removed once no post is part of one.
+        let blog = Blog::new(Config::default(), vec![post("alone", None, None)]);
+        let actions = blog.generate_actions("dist")?;
+        assert!(actions.iter().any(|action| matches!(action,
+            FsAction::DeleteDir { path, .. } if path == Path::new("dist/series")
+        )));
+        assert!(!actions.iter().any(|action| matches!(action,
+            FsAction::WriteFile { path, .. } if path.starts_with("dist/series")
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn drafts_skipped_and_unlisted_posts_hidden() -> Result<()> {
+        let post = |name: &str, draft: bool, unlisted: bool| Post {
+            tags: vec!["tag".into()],
+            draft,
+            unlisted,
+            ..Post::for_test(name, Local::now().into())
+        };
+        let mut blog = Blog::new(
+            Config::default(),
+            vec![
+                post("published", false, false),
+                post("draft", true, false),
+                post("unlisted", false, true),
+            ],
+        );
+
+        let written = |actions: &[FsAction]| -> Vec<(PathBuf, String)> {
+            actions
+                .iter()
+                .filter_map(|action| match action {
+                    FsAction::WriteFile { path, content } => Some((path.clone(), content.clone())),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let files = written(&blog.generate_actions("dist")?);
+        let paths: Vec<_> = files.iter().map(|(path, _)| path.as_path()).collect();
+        assert!(paths.contains(&Path::new("dist/published/index.html")));
+        assert!(paths.contains(&Path::new("dist/unlisted/index.html")));
+        assert!(!paths.contains(&Path::new("dist/draft/index.html")));
+        for (path, content) in files.iter() {
+            if !path.starts_with("dist/unlisted") {
+                assert!(!content.contains("unlisted"), "{:?} lists it", path);
+            }
+            assert!(!content.contains("draft"), "{:?} lists it", path);
+        }
+
+        blog.drafts = true;
+        let files = written(&blog.generate_actions("dist")?);
+        assert!(files
+            .iter()
+            .any(|(path, _)| path == Path::new("dist/draft/index.html")));
+        Ok(())
+    }
+
+    #[test]
+    fn future_posts_skipped() -> Result<()> {
+        let mut blog = Blog::new(
+            Config::default(),
+            vec![
+                Post::for_test("today", Local::now().into()),
+                Post::for_test(
+                    "scheduled",
+                    (Local::now() + chrono::Duration::days(1)).into(),
+                ),
+            ],
+        );
+
+        let is_written = |actions: &[FsAction], name: &str| {
+            actions.iter().any(|action| {
+                matches!(action, FsAction::WriteFile { path, .. }
+                    if path == &Path::new("dist").join(name).join("index.html"))
+            })
+        };
+
+        let actions = blog.generate_actions("dist")?;
+        assert!(is_written(&actions, "today"));
+        assert!(!is_written(&actions, "scheduled"));
+        assert!(matches!(actions.last(),
+            Some(FsAction::WriteFile { content, .. }) if !content.contains("scheduled")
+        ));
+
+        blog.future = true;
+        let actions = blog.generate_actions("dist")?;
+        assert!(is_written(&actions, "scheduled"));
+        Ok(())
+    }
+
+    #[test]
+    fn posts_of_the_same_day_sorted_by_time() -> Result<()> {
+        let post = |name: &str, created: &str| {
+            Post::for_test(name, chrono::DateTime::parse_from_rfc3339(created).unwrap())
+        };
+        let blog = Blog::new(
+            Config::default(),
+            vec![
+                post("morning", "2020-02-20T09:00:00+01:00"),
+                post("evening", "2020-02-20T20:00:00+01:00"),
+                post("noon-elsewhere", "2020-02-20T12:00:00+05:00"),
+            ],
+        );
+
+        let actions = blog.generate_actions("dist")?;
+        let feed = match actions.last() {
+            Some(FsAction::WriteFile { content, .. }) => content,
+            _ => panic!("expected the feed to be written last"),
+        };
+        let position = |name: &str| feed.find(&format!("<title>{}</title>", name)).unwrap();
+        assert!(position("evening") < position("morning"));
+        assert!(position("morning") < position("noon-elsewhere"));
+        assert!(feed.contains("<published>2020-02-20T20:00:00+01:00</published>"));
+        assert!(feed.contains("<updated>2020-02-20T20:00:00+01:00</updated>"));
+        Ok(())
+    }
+
+    #[test]
+    fn posts_link_to_their_neighbours() -> Result<()> {
+        let post = |name: &str, day: u32, unlisted: bool| {
+            let date = Local.ymd(2020, 2, day).and_hms(0, 0, 0).into();
+            Post {
+                title_html: format!("<em>{}</em>", name),
+                unlisted,
+                ..Post::for_test(name, date)
+            }
+        };
+        let blog = Blog::new(
+            Config::default(),
+            vec![
+                post("oldest", 1, false),
+                post("hidden", 2, true),
+                post("middle", 3, false),
+                post("newest", 4, false),
+            ],
+        );
+
+        let middle = blog.render_post(&blog.posts[2])?;
+        assert!(middle.contains(
+            "<a class=\"prev\" rel=\"prev\" href=\"../oldest/index.html\">← <em>oldest</em></a>"
+        ));
+        assert!(middle.contains(
+            "<a class=\"next\" rel=\"next\" href=\"../newest/index.html\"><em>newest</em> →</a>"
+        ));
+        assert!(middle.contains("<a class=\"index\" href=\"../index.html\">All posts</a>"));
+
+        let newest = blog.render_post(&blog.posts[3])?;
+        assert!(newest.contains("class=\"prev\""));
+        assert!(!newest.contains("class=\"next\""));
+
+        // Unlisted posts are not linked from others, and only link back to the index.
+        let hidden = blog.render_post(&blog.posts[1])?;
+        assert!(hidden.contains("class=\"index\""));
+        assert!(!hidden.contains("class=\"prev\"") && !hidden.contains("class=\"next\""));
+
+        let (previous, next) = blog.adjacent_posts(&blog.posts[0]);
+        assert!(previous.is_none());
+        assert_eq!(next.map(|post| post.title.as_str()), Some("middle"));
+        Ok(())
+    }
+
+    #[test]
+    fn alias_redirects_generated() -> Result<()> {
+        let post = |name: &str, aliases: &[&str]| Post {
+            aliases: aliases.iter().map(|&alias| alias.into()).collect(),
+            ..Post::for_test(name, Local::now().into())
+        };
+
+        let blog = Blog::new(
+            Config::default(),
+            vec![post("moved", &["old", "2020/older"])],
+        );
+        let actions = blog.generate_actions("dist")?;
+        let redirect = actions.iter().find_map(|action| match action {
+            FsAction::WriteFile { path, content }
+                if path == Path::new("dist/2020/older/index.html") =>
+            {
+                Some(content)
+            }
+            _ => None,
+        });
+        assert!(redirect.is_some_and(|content| content.contains(
+            "<meta http-equiv=\"refresh\" content=\"0; url=../../moved/index.html\" />"
+        ) && content
+            .contains("<link rel=\"canonical\" href=\"http://localhost/moved/index.html\" />")));
+        assert!(actions.iter().any(|action| matches!(action,
+            FsAction::CreateDir { path, exists_ok: true } if path == Path::new("dist/2020")
+        )));
+        assert!(actions.iter().any(|action| matches!(action,
+            FsAction::WriteFile { path, .. } if path == Path::new("dist/old/index.html")
+        )));
+
+        let collides = |posts| Blog::new(Config::default(), posts).generate_actions("dist");
+        assert!(collides(vec![post("a", &["css"])]).is_err());
+        assert!(collides(vec![post("a", &["tags/old"])]).is_err());
+        assert!(collides(vec![post("a", &["b/old"]), post("b", &[])]).is_err());
+        assert!(collides(vec![post("a", &["old"]), post("b", &["old"])]).is_err());
+        let draft = Post {
+            draft: true,
+            ..post("draft", &[])
+        };
+        assert!(collides(vec![post("a", &["draft"]), draft]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn incremental_actions_only_touch_changed_posts() -> Result<()> {
+        let post = |name: &str| Post {
+            source: format!("content/{}", name).into(),
+            assets: vec![format!("content/{}/image.png", name).into()],
+            ..Post::for_test(name, Local::now().into())
+        };
+        let mut blog = Blog::new(Config::default(), vec![post("changed"), post("unchanged")]);
+        blog.css_path = Some("content/style.css".into());
+
+        let actions = blog.generate_incremental_actions(
+            "dist",
+            &[PostChange {
+                source: "content/changed".into(),
+                assets: vec!["content/changed/removed.png".into()],
+            }],
+        )?;
+
+        assert_eq!(actions.len(), 7);
+        assert!(matches!(&actions[0],
+            FsAction::CreateDir {
+                path,
+                exists_ok: true,
+            } if path == Path::new("dist/changed")
+        ));
+        assert!(matches!(&actions[1],
+            FsAction::WriteFile { path, .. } if path == Path::new("dist/changed/index.html")
+        ));
+        assert!(matches!(&actions[2],
+            FsAction::DeleteFile {
+                path,
+                not_exists_ok: true,
+            } if path == Path::new("dist/changed/removed.png")
+        ));
+        assert!(matches!(&actions[3],
+            FsAction::WriteFile { path, content }
+                if path == Path::new("dist/index.html") && content.contains("unchanged")
+        ));
+        assert!(matches!(&actions[6],
+            FsAction::WriteFile { path, content }
+                if path == Path::new("dist/atom.xml") && content.contains("unchanged")
+        ));
+        Ok(())
+    }
 }
@@ -0,0 +1,297 @@
+use pagong::{CONFIG_FILE_NAME, DEFAULT_CONTENT_PATH, DEFAULT_OUTPUT_PATH, DEFAULT_SERVE_PORT};
+
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// A static site generator for slow connections.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Only print errors.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Print more information about what is being done. Can be repeated.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Generate the site from the content directory (the default if no command is given).
+    Build(BuildCommandArgs),
+    /// Build the site and keep updating it as the content changes.
+    Watch(BuildArgs),
+    /// Build a preview of the site and serve it locally, rebuilding and reloading pages on
+    /// changes.
+    Serve(ServeArgs),
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct BuildArgs {
+    /// Directory containing the posts and other sources of the blog.
+    #[arg(short, long, value_name = "DIR", default_value = DEFAULT_CONTENT_PATH)]
+    pub content: PathBuf,
+
+    /// Directory where the generated site will be written to [default: dist, or a temporary
+    /// directory when serving].
+    #[arg(short, long, value_name = "DIR")]
+    pub output: Option<PathBuf>,
+
+    /// Configuration file to use [default: pagong.toml next to the content directory].
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Also generate the posts marked as drafts.
+    #[arg(long)]
+    pub drafts: bool,
+
+    /// Also generate the posts with a creation date in the future.
+    #[arg(long)]
+    pub future: bool,
+
+    /// Fail on unknown keys, missing values or invalid values in the metadata of posts.
+    #[arg(long)]
+    pub strict: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct BuildCommandArgs {
+    #[command(flatten)]
+    pub build: BuildArgs,
+
+    /// Print what would be done to the output directory instead of doing it.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// How to print the actions of a dry run.
+    #[arg(long, value_enum, default_value_t = PlanFormat::Text, requires = "dry_run")]
+    pub format: PlanFormat,
+
+    /// Fail if loading any post (its HTML and every file it loads) takes more kilobytes.
+    #[arg(long, value_name = "KB", conflicts_with = "dry_run")]
+    pub max_page_weight: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum PlanFormat {
+    /// One line per action, followed by a summary.
+    Text,
+    /// A JSON array with one object per action.
+    Json,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ServeArgs {
+    #[command(flatten)]
+    pub build: BuildArgs,
+
+    /// Port on localhost to serve the site from.
+    #[arg(short, long, default_value_t = DEFAULT_SERVE_PORT)]
+    pub port: u16,
+}
+
+impl Default for BuildArgs {
+    fn default() -> Self {
+        Self {
+            content: DEFAULT_CONTENT_PATH.into(),
+            output: None,
+            config: None,
+            drafts: false,
+            future: false,
+            strict: false,
+        }
+    }
+}
+
+impl Default for BuildCommandArgs {
+    fn default() -> Self {
+        Self {
+            build: BuildArgs::default(),
+            dry_run: false,
+            format: PlanFormat::Text,
+            max_page_weight: None,
+        }
+    }
+}
+
+impl BuildArgs {
+    /// The directory to write the site to.
+    pub fn output_dir(&self) -> PathBuf {
+        self.output
+            .clone()
+            .unwrap_or_else(|| DEFAULT_OUTPUT_PATH.into())
+    }
+
+    /// The path to the configuration file, and whether it was explicitly requested.
+    pub fn config_path(&self) -> (PathBuf, bool) {
+        match &self.config {
+            Some(path) => (path.clone(), true),
+            None => (
+                self.content
+                    .parent()
+                    .map(|parent| parent.join(CONFIG_FILE_NAME))
+                    .unwrap_or_else(|| CONFIG_FILE_NAME.into()),
+                false,
+            ),
+        }
+    }
+}
+
+impl ServeArgs {
+    /// The directory to write the preview to. Unless another one is requested, it's not the
+    /// one `build` writes to, so that the preview (which points to the local server and
+    /// reloads itself) is never deployed by mistake.
+    pub fn output_dir(&self) -> PathBuf {
+        self.build
+            .output
+            .clone()
+            .unwrap_or_else(|| std::env::temp_dir().join(format!("pagong-serve-{}", self.port)))
+    }
+}
+
+impl Cli {
+    pub fn level_filter(&self) -> LevelFilter {
+        if self.quiet {
+            return LevelFilter::Error;
+        }
+        match self.verbose {
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    }
+}
+
+/// Minimal logger printing this crate's enabled records to the standard error output.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+            && metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Error => eprintln!("error: {}", record.args()),
+            Level::Warn => eprintln!("warning: {}", record.args()),
+            Level::Info => eprintln!("{}", record.args()),
+            Level::Debug | Level::Trace => eprintln!("  {}", record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+pub fn init_logger(level: LevelFilter) {
+    log::set_logger(&LOGGER).expect("Logger should only be initialized once");
+    log::set_max_level(level);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_command_is_allowed() {
+        let cli = Cli::try_parse_from(["pagong"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.level_filter(), LevelFilter::Info);
+    }
+
+    #[test]
+    fn build_paths_parsed() {
+        let cli = Cli::try_parse_from([
+            "pagong",
+            "build",
+            "--content",
+            "site/src",
+            "-o",
+            "public",
+            "--drafts",
+            "--future",
+            "--strict",
+            "-vv",
+        ])
+        .unwrap();
+
+        let Some(Command::Build(BuildCommandArgs { build: args, .. })) = &cli.command else {
+            panic!("expected build command");
+        };
+        assert_eq!(args.content, PathBuf::from("site/src"));
+        assert_eq!(args.output_dir(), PathBuf::from("public"));
+        assert!(args.drafts);
+        assert!(args.future);
+        assert!(args.strict);
+        assert_eq!(
+            args.config_path(),
+            (PathBuf::from("site").join(CONFIG_FILE_NAME), false)
+        );
+        assert_eq!(cli.level_filter(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn dry_run_parsed() {
+        let cli =
+            Cli::try_parse_from(["pagong", "build", "--dry-run", "--format", "json"]).unwrap();
+
+        let Some(Command::Build(args)) = &cli.command else {
+            panic!("expected build command");
+        };
+        assert!(args.dry_run);
+        assert_eq!(args.format, PlanFormat::Json);
+        assert!(Cli::try_parse_from(["pagong", "build", "--format", "json"]).is_err());
+    }
+
+    #[test]
+    fn max_page_weight_parsed() {
+        let cli = Cli::try_parse_from(["pagong", "build", "--max-page-weight", "50"]).unwrap();
+
+        let Some(Command::Build(args)) = &cli.command else {
+            panic!("expected build command");
+        };
+        assert_eq!(args.max_page_weight, Some(50));
+        assert!(Cli::try_parse_from(["pagong", "build", "--max-page-weight", "-1"]).is_err());
+        assert!(
+            Cli::try_parse_from(["pagong", "build", "--dry-run", "--max-page-weight", "50"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn serve_port_parsed() {
+        let cli =
+            Cli::try_parse_from(["pagong", "serve", "--port", "1234", "-o", "preview"]).unwrap();
+
+        let Some(Command::Serve(args)) = &cli.command else {
+            panic!("expected serve command");
+        };
+        assert_eq!(args.port, 1234);
+        assert_eq!(args.output_dir(), PathBuf::from("preview"));
+        assert_eq!(args.build.content, PathBuf::from(DEFAULT_CONTENT_PATH));
+
+        // The preview is kept apart from the site that is built for deploying.
+        let cli = Cli::try_parse_from(["pagong", "serve"]).unwrap();
+        let Some(Command::Serve(args)) = &cli.command else {
+            panic!("expected serve command");
+        };
+        assert_ne!(args.output_dir(), PathBuf::from(DEFAULT_OUTPUT_PATH));
+        assert_eq!(args.build.output_dir(), PathBuf::from(DEFAULT_OUTPUT_PATH));
+    }
+
+    #[test]
+    fn quiet_and_verbose_conflict() {
+        assert!(Cli::try_parse_from(["pagong", "build", "-q", "-v"]).is_err());
+    }
+}
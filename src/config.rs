@@ -0,0 +1,316 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+/// Site-wide settings, loaded from the configuration file next to the content directory.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The title of the blog, used in the index page and the feed.
+    pub title: String,
+    /// Absolute URL where the generated site will be hosted, without a trailing slash.
+    pub base_url: String,
+    /// Author used for entries in the feed. Defaults to the blog's title if missing.
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Language tag of the content (as in `<html lang="...">`).
+    #[serde(default = "default_language")]
+    pub language: String,
+    /// Short description of the blog, used in the index page and the feed.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Time zone of the dates written without an offset (such as "Europe/Madrid").
+    /// Defaults to the time zone of the machine generating the site.
+    #[serde(default)]
+    pub timezone: Option<Tz>,
+    /// Whether the dates of posts come from the history of the git repository containing them,
+    /// instead of the file system (whose dates are reset by every fresh clone).
+    #[serde(default)]
+    pub git_dates: bool,
+    /// Maximum number of characters of the summaries taken from the first paragraph of posts.
+    #[serde(default = "default_summary_length")]
+    pub summary_length: usize,
+    /// Directory of the theme with the templates, stylesheet and static files of the site,
+    /// relative to the configuration file. The built-in theme is used if missing.
+    #[serde(default)]
+    pub theme: Option<PathBuf>,
+    /// Whether the stylesheet is minified and included in every page instead of linked, so
+    /// that each page can be loaded with a single request.
+    #[serde(default)]
+    pub inline_css: bool,
+    /// Whether the rules of the inlined stylesheet that can't match any element of a page are
+    /// left out of it, so that every page only carries the styles it needs.
+    #[serde(default)]
+    pub prune_css: bool,
+    /// Whether the text files of the site are also written compressed with gzip and brotli,
+    /// for servers that can send them without compressing them on every request.
+    #[serde(default)]
+    pub precompress: bool,
+    /// Whether the JPEG and PNG images of posts are resized and encoded again, instead of being
+    /// copied as they are.
+    #[serde(default)]
+    pub optimize_images: bool,
+    /// Maximum width in pixels of the optimized images. Wider images are scaled down.
+    #[serde(default = "default_image_max_width")]
+    pub image_max_width: u32,
+    /// Quality from 1 to 100 of the optimized JPEG images.
+    #[serde(default = "default_image_quality")]
+    pub image_quality: u8,
+    /// Whether the optimized PNG images are also offered in the lossless WebP format. JPEG
+    /// images never are, since they're rarely smaller without losses.
+    #[serde(default)]
+    pub image_webp: bool,
+}
+
+fn default_language() -> String {
+    "en".to_string()
+}
+
+fn default_summary_length() -> usize {
+    200
+}
+
+fn default_image_max_width() -> u32 {
+    1200
+}
+
+fn default_image_quality() -> u8 {
+    80
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            title: "pagong".to_string(),
+            base_url: "http://localhost".to_string(),
+            author: None,
+            language: default_language(),
+            description: None,
+            timezone: None,
+            git_dates: false,
+            summary_length: default_summary_length(),
+            theme: None,
+            inline_css: false,
+            prune_css: false,
+            precompress: false,
+            optimize_images: false,
+            image_max_width: default_image_max_width(),
+            image_quality: default_image_quality(),
+            image_webp: false,
+        }
+    }
+}
+
+impl Config {
+    /// Load and validate the configuration from the given file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .context(format!("Could not read configuration file '{:?}'", path))?;
+        let mut config = Self::from_toml(&contents)
+            .context(format!("Invalid configuration file '{:?}'", path))?;
+        if let (Some(theme), Some(dir)) = (&mut config.theme, path.parent()) {
+            *theme = dir.join(&*theme);
+        }
+        Ok(config)
+    }
+
+    /// Parse and validate the configuration from its TOML representation.
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        let mut config: Self = toml::from_str(contents)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check that all values are sensible, normalizing them where possible.
+    fn validate(&mut self) -> Result<()> {
+        self.title = self.title.trim().to_string();
+        if self.title.is_empty() {
+            return Err(anyhow!("The 'title' must not be empty"));
+        }
+
+        while self.base_url.ends_with('/') {
+            self.base_url.pop();
+        }
+        let without_scheme = self
+            .base_url
+            .strip_prefix("https://")
+            .or_else(|| self.base_url.strip_prefix("http://"))
+            .ok_or_else(|| {
+                anyhow!(
+                    "The 'base_url' {:?} must be an absolute URL starting with http:// or https://",
+                    self.base_url
+                )
+            })?;
+        if without_scheme.is_empty() {
+            return Err(anyhow!("The 'base_url' {:?} has no host", self.base_url));
+        }
+        if without_scheme.contains(|c: char| c == '?' || c == '#' || c.is_whitespace()) {
+            return Err(anyhow!(
+                "The 'base_url' {:?} must not contain a query, fragment or whitespace",
+                self.base_url
+            ));
+        }
+
+        if let Some(author) = &self.author {
+            if author.trim().is_empty() {
+                return Err(anyhow!("The 'author' must not be empty if present"));
+            }
+        }
+
+        if self.language.is_empty()
+            || !self
+                .language
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        {
+            return Err(anyhow!(
+                "The 'language' {:?} is not a valid language tag (such as \"en\" or \"es-ES\")",
+                self.language
+            ));
+        }
+
+        if self.summary_length == 0 {
+            return Err(anyhow!("The 'summary_length' must be greater than zero"));
+        }
+
+        if self.prune_css && !self.inline_css {
+            return Err(anyhow!(
+                "The 'prune_css' option only works together with 'inline_css'"
+            ));
+        }
+
+        if self.image_max_width == 0 {
+            return Err(anyhow!("The 'image_max_width' must be greater than zero"));
+        }
+        if !(1..=100).contains(&self.image_quality) {
+            return Err(anyhow!("The 'image_quality' must be between 1 and 100"));
+        }
+        if self.image_webp && !self.optimize_images {
+            return Err(anyhow!(
+                "The 'image_webp' option only works together with 'optimize_images'"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The author to use in the feed when a post does not specify one.
+    pub fn author(&self) -> &str {
+        self.author.as_deref().unwrap_or(&self.title)
+    }
+
+    /// Build the absolute URL for the given path relative to the blog's root.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path.trim_start_matches('/'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_config_parsed() -> Result<()> {
+        let config = Config::from_toml(
+            r#"
+title = "My blog"
+base_url = "https://example.com/blog/"
+author = "Me"
+language = "es-ES"
+description = "Things I write about"
+timezone = "Europe/Madrid"
+git_dates = true
+summary_length = 100
+theme = "themes/dark"
+inline_css = true
+prune_css = true
+precompress = true
+optimize_images = true
+image_max_width = 800
+image_quality = 70
+image_webp = true
+"#,
+        )?;
+
+        assert_eq!(config.title, "My blog");
+        assert_eq!(config.base_url, "https://example.com/blog");
+        assert_eq!(config.author(), "Me");
+        assert_eq!(config.language, "es-ES");
+        assert_eq!(config.description.as_deref(), Some("Things I write about"));
+        assert_eq!(config.timezone, Some(Tz::Europe__Madrid));
+        assert!(config.git_dates);
+        assert_eq!(config.summary_length, 100);
+        assert_eq!(config.theme, Some(PathBuf::from("themes/dark")));
+        assert!(config.inline_css);
+        assert!(config.prune_css);
+        assert!(config.precompress);
+        assert!(config.optimize_images);
+        assert_eq!(config.image_max_width, 800);
+        assert_eq!(config.image_quality, 70);
+        assert!(config.image_webp);
+        assert_eq!(config.url("atom.xml"), "https://example.com/blog/atom.xml");
+        Ok(())
+    }
+
+    #[test]
+    fn minimal_config_uses_defaults() -> Result<()> {
+        let config = Config::from_toml(
+            r#"
+title = "Minimal"
+base_url = "http://example.com"
+"#,
+        )?;
+
+        assert_eq!(config.author(), "Minimal");
+        assert_eq!(config.language, "en");
+        assert_eq!(config.description, None);
+        assert_eq!(config.timezone, None);
+        assert!(!config.git_dates);
+        assert_eq!(config.summary_length, 200);
+        assert_eq!(config.theme, None);
+        assert!(!config.inline_css);
+        assert!(!config.prune_css);
+        assert!(!config.precompress);
+        assert!(!config.optimize_images);
+        assert_eq!(config.image_max_width, 1200);
+        assert_eq!(config.image_quality, 80);
+        assert!(!config.image_webp);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_config_rejected() {
+        assert!(Config::from_toml("title = \"No URL\"").is_err());
+        assert!(Config::from_toml("title = \"\"\nbase_url = \"https://a.b\"").is_err());
+        assert!(Config::from_toml("title = \"Bad\"\nbase_url = \"example.com\"").is_err());
+        assert!(Config::from_toml("title = \"Bad\"\nbase_url = \"https://a.b/?q\"").is_err());
+        assert!(Config::from_toml(
+            "title = \"Bad\"\nbase_url = \"https://a.b\"\nlanguage = \"e n\""
+        )
+        .is_err());
+        assert!(Config::from_toml(
+            "title = \"Bad\"\nbase_url = \"https://a.b\"\ntimezone = \"Mars/Olympus\""
+        )
+        .is_err());
+        assert!(
+            Config::from_toml("title = \"Bad\"\nbase_url = \"https://a.b\"\nprune_css = true")
+                .is_err()
+        );
+        assert!(Config::from_toml(
+            "title = \"Bad\"\nbase_url = \"https://a.b\"\nimage_quality = 0"
+        )
+        .is_err());
+        assert!(Config::from_toml(
+            "title = \"Bad\"\nbase_url = \"https://a.b\"\nimage_webp = true"
+        )
+        .is_err());
+        assert!(
+            Config::from_toml("title = \"Typo\"\nbase_url = \"https://a.b\"\nauthr = \"Me\"")
+                .is_err()
+        );
+    }
+}
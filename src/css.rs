@@ -0,0 +1,340 @@
+//! Processing of the stylesheet before it is included in the pages.
+
+use std::collections::HashSet;
+
+use crate::markup;
+
+/// Whether the whitespace next to the character can be removed without changing the meaning
+/// of the stylesheet.
+fn is_separator(c: char) -> bool {
+    matches!(c, '{' | '}' | ';' | ',' | '>')
+}
+
+/// Remove the comments and all the whitespace which is not needed from a stylesheet.
+pub(crate) fn minify(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut chars = css.chars().peekable();
+    let mut pending_space = false;
+
+    while let Some(c) = chars.next() {
+        if c == '/' && chars.peek() == Some(&'*') {
+            chars.next();
+            let mut previous = '\0';
+            for c in chars.by_ref() {
+                if previous == '*' && c == '/' {
+                    break;
+                }
+                previous = c;
+            }
+            pending_space = true;
+            continue;
+        }
+        if c.is_whitespace() {
+            pending_space = true;
+            continue;
+        }
+
+        // A space is still needed between words, and before a colon (which could be
+        // the start of a pseudo-class in a selector, as in `a :hover`).
+        if pending_space
+            && !is_separator(c)
+            && out
+                .chars()
+                .last()
+                .is_some_and(|last| !is_separator(last) && last != ':')
+        {
+            out.push(' ');
+        }
+        pending_space = false;
+
+        if c == '}' && out.ends_with(';') {
+            out.pop();
+        }
+        out.push(c);
+
+        if c == '"' || c == '\'' {
+            // Strings are kept as they are, including their escapes.
+            let mut escaped = false;
+            for s in chars.by_ref() {
+                out.push(s);
+                if escaped {
+                    escaped = false;
+                } else if s == '\\' {
+                    escaped = true;
+                } else if s == c {
+                    break;
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// The tag names, classes and identifiers used by the elements of a page.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct PageElements {
+    tags: HashSet<String>,
+    classes: HashSet<String>,
+    ids: HashSet<String>,
+}
+
+impl PageElements {
+    /// Find the elements of the given HTML page.
+    pub(crate) fn from_html(html: &str) -> Self {
+        let mut elements = Self::default();
+        for tag in markup::tags(html) {
+            for (name, value) in tag.attributes.iter() {
+                match name.as_ref() {
+                    "class" => elements
+                        .classes
+                        .extend(value.split_whitespace().map(String::from)),
+                    "id" => {
+                        elements.ids.insert(value.to_string());
+                    }
+                    _ => {}
+                }
+            }
+            elements.tags.insert(tag.name);
+        }
+        elements
+    }
+
+    /// Whether the compound selector (with no combinators) could match some element.
+    ///
+    /// Only its type, classes and identifier are checked. Anything else (such as attributes
+    /// or pseudo-classes) is assumed to match, so that rules are never removed by mistake.
+    fn may_match_compound(&self, compound: &str) -> bool {
+        let mut chars = compound.char_indices().peekable();
+        let ident = |start: usize| {
+            let len = compound[start..]
+                .find(|c: char| !(c.is_alphanumeric() || matches!(c, '-' | '_' | '\\')))
+                .unwrap_or(compound.len() - start);
+            &compound[start..start + len]
+        };
+        while let Some((i, c)) = chars.next() {
+            let (name, matches) = match c {
+                '.' => (ident(i + 1), self.classes.contains(ident(i + 1))),
+                '#' => (ident(i + 1), self.ids.contains(ident(i + 1))),
+                ':' => {
+                    // Skip the name of the pseudo-class and its arguments, if any.
+                    let mut depth = 0;
+                    while let Some(&(_, c)) = chars.peek() {
+                        match c {
+                            '(' => depth += 1,
+                            ')' if depth > 0 => depth -= 1,
+                            _ if depth == 0 && !(c.is_alphanumeric() || c == '-' || c == ':') => {
+                                break
+                            }
+                            _ => {}
+                        }
+                        chars.next();
+                    }
+                    continue;
+                }
+                '[' => {
+                    for (_, c) in chars.by_ref() {
+                        if c == ']' {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                c if i == 0 && c.is_alphabetic() => {
+                    let name = ident(i);
+                    (name, self.tags.contains(&name.to_ascii_lowercase()))
+                }
+                _ => continue,
+            };
+            if !matches {
+                return false;
+            }
+            for _ in name.chars() {
+                chars.next();
+            }
+        }
+        true
+    }
+
+    /// Whether the selector could match some element, which is only false if one of its
+    /// parts certainly doesn't.
+    fn may_match(&self, selector: &str) -> bool {
+        split_top_level(selector, |c| {
+            c.is_whitespace() || matches!(c, '>' | '+' | '~')
+        })
+        .iter()
+        .filter(|compound| !compound.is_empty())
+        .all(|compound| self.may_match_compound(compound))
+    }
+}
+
+/// Split the text at the characters for which `is_separator` returns true, unless they are
+/// inside parenthesis, brackets or strings.
+fn split_top_level(text: &str, is_separator: impl Fn(char) -> bool) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[') => depth += 1,
+            (None, ')' | ']') => depth -= 1,
+            (None, c) if depth == 0 && is_separator(c) => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Find the end of the block starting at `start` (right after its opening brace), which is the
+/// position of its closing brace.
+fn block_end(css: &str, start: usize) -> usize {
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, c) in css[start..].char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') if depth == 0 => return start + i,
+            (None, '}') => depth -= 1,
+            _ => {}
+        }
+    }
+    css.len()
+}
+
+/// Remove the rules of a minified stylesheet whose selectors can't match any of the elements
+/// of a page, along with the selectors of a rule that can't.
+pub(crate) fn prune(css: &str, elements: &PageElements) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while !rest.is_empty() {
+        let open = match rest.find(['{', ';']) {
+            // Statements such as `@import url(a.css);` are always kept.
+            Some(i) if rest.as_bytes()[i] == b';' => {
+                out.push_str(&rest[..=i]);
+                rest = &rest[i + 1..];
+                continue;
+            }
+            Some(i) => i,
+            None => {
+                out.push_str(rest);
+                break;
+            }
+        };
+        let prelude = &rest[..open];
+        let close = block_end(rest, open + 1);
+        let block = &rest[open + 1..close];
+        rest = rest.get(close + 1..).unwrap_or("");
+
+        if let Some(at_rule) = prelude.strip_prefix('@') {
+            if ["media", "supports", "document", "layer"]
+                .iter()
+                .any(|name| at_rule.starts_with(name))
+            {
+                let inner = prune(block, elements);
+                if !inner.is_empty() {
+                    out.push_str(&format!("{}{{{}}}", prelude, inner));
+                }
+            } else {
+                // Such as `@font-face` or `@keyframes`, which are not about elements.
+                out.push_str(&format!("{}{{{}}}", prelude, block));
+            }
+            continue;
+        }
+
+        let selectors: Vec<_> = split_top_level(prelude, |c| c == ',')
+            .into_iter()
+            .filter(|selector| elements.may_match(selector))
+            .collect();
+        if !selectors.is_empty() {
+            out.push_str(&format!("{}{{{}}}", selectors.join(","), block));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn css_minified() {
+        let css = "/* containers */
+
+body {
+    margin: 0;
+}
+
+h1 :hover, main > p {
+    font-family: \"Open  Sans\", sans-serif;
+    border-bottom: 0 dashed rgba(0, 0, 0, 0);
+}
+
+@media (max-width: 600px) and (orientation: portrait) {
+    a::after { content: '/* not a comment */'; }
+}
+";
+        assert_eq!(
+            minify(css),
+            "body{margin:0}h1 :hover,main>p{font-family:\"Open  Sans\",sans-serif;\
+             border-bottom:0 dashed rgba(0,0,0,0)}@media (max-width:600px) and \
+             (orientation:portrait){a::after{content:'/* not a comment */'}}"
+        );
+    }
+
+    #[test]
+    fn page_elements_found() {
+        let elements = PageElements::from_html(
+            "<!DOCTYPE html>\n<HTML lang=\"en\"><body>\n<!-- a comment -->\n\
+             <h1 class=\"title  big\" id='top'>Hi</h1><img src=x.png alt=\"a > b\"/>\
+             <input disabled class=wide></body></html>",
+        );
+        let set = |items: &[&str]| items.iter().map(|&item| item.to_string()).collect();
+        assert_eq!(
+            elements,
+            PageElements {
+                tags: set(&["html", "body", "h1", "img", "input"]),
+                classes: set(&["title", "big", "wide"]),
+                ids: set(&["top"]),
+            }
+        );
+    }
+
+    #[test]
+    fn unused_rules_pruned() {
+        let elements = PageElements::from_html(
+            "<main><h1 class=\"title\">Hi</h1><p>Text <a href=\"#\">link</a></p></main>",
+        );
+        let css = minify(
+            "body { margin: 0 }
+            main, table { max-width: 720px }
+            h1.title { font-size: 2em }
+            h1:not(.title):hover .anchor { opacity: 1 }
+            p, li { line-height: 1.5 }
+            a:hover, a[href^=\"http\"] { color: red }
+            a:hover.unused { color: green }
+            tr:nth-child(even), .footnote:target, #toc > li { color: blue }
+            ::selection { color: white }
+            @media (max-width: 600px) { table { width: 100% } main { padding: 0 } }
+            @font-face { font-family: X; src: url(x.woff2) }
+            @import url(\"print.css\");",
+        );
+        assert_eq!(
+            prune(&css, &elements),
+            "main{max-width:720px}h1.title{font-size:2em}p{line-height:1.5}\
+             a:hover,a[href^=\"http\"]{color:red}::selection{color:white}\
+             @media (max-width:600px){main{padding:0}}\
+             @font-face{font-family:X;src:url(x.woff2)}@import url(\"print.css\");"
+        );
+    }
+}
@@ -0,0 +1,321 @@
+//! Parsing of the metadata written at the start of a post, either as YAML
+//! (between `---` lines) or TOML (between `+++` lines).
+
+use std::ops::Range;
+
+use anyhow::{anyhow, Context, Result};
+
+/// A metadata value, regardless of the format it was written in.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum MetaValue {
+    Text(String),
+    Bool(bool),
+    Integer(i64),
+    List(Vec<MetaValue>),
+}
+
+impl MetaValue {
+    /// The value as text, if it's not a list.
+    pub(crate) fn as_text(&self) -> Option<String> {
+        match self {
+            MetaValue::Text(text) => Some(text.trim().to_string()),
+            MetaValue::Bool(value) => Some(value.to_string()),
+            MetaValue::Integer(value) => Some(value.to_string()),
+            MetaValue::List(_) => None,
+        }
+    }
+
+    /// The value as a boolean. Text is accepted too, as written in the meta block.
+    pub(crate) fn as_bool(&self) -> Option<bool> {
+        match self {
+            MetaValue::Bool(value) => Some(*value),
+            MetaValue::Text(text) => match text.trim().to_lowercase().as_ref() {
+                "true" | "yes" => Some(true),
+                "false" | "no" => Some(false),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The value as a list of text. Text is split on commas, so that lists can also be
+    /// written in the meta block.
+    pub(crate) fn as_list(&self) -> Option<Vec<String>> {
+        let items = match self {
+            MetaValue::List(values) => values
+                .iter()
+                .map(MetaValue::as_text)
+                .collect::<Option<Vec<_>>>()?,
+            value => value
+                .as_text()?
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .collect(),
+        };
+        Some(items.into_iter().filter(|item| !item.is_empty()).collect())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Format {
+    Yaml,
+    Toml,
+}
+
+/// Front matter found at the start of a post.
+#[derive(Debug, PartialEq)]
+pub(crate) struct FrontMatter<'a> {
+    pub(crate) format: Format,
+    /// The text between the delimiters.
+    pub(crate) contents: &'a str,
+    /// The range of the whole front matter in the post, including the delimiters.
+    pub(crate) range: Range<usize>,
+}
+
+impl FrontMatter<'_> {
+    /// The line of the post (starting at 1) where `key` is defined, or the first line
+    /// of the front matter if it can't be found.
+    pub(crate) fn line_of(&self, key: &str) -> usize {
+        let separator = match self.format {
+            Format::Yaml => ':',
+            Format::Toml => '=',
+        };
+        self.contents
+            .lines()
+            .position(|line| {
+                let line = line.trim_start().trim_start_matches(['"', '\'']);
+                line.strip_prefix(key).is_some_and(|rest| {
+                    rest.trim_start_matches(['"', '\''])
+                        .trim_start()
+                        .starts_with(separator)
+                })
+            })
+            // The contents start after the line with the opening delimiter.
+            .map_or(1, |i| i + 2)
+    }
+}
+
+/// Find the front matter at the very start of the markdown, if any.
+pub(crate) fn find(markdown: &str) -> Option<FrontMatter<'_>> {
+    let (format, delimiter) = if markdown.starts_with("---") {
+        (Format::Yaml, "---")
+    } else if markdown.starts_with("+++") {
+        (Format::Toml, "+++")
+    } else {
+        return None;
+    };
+
+    // The opening delimiter must be alone in its line.
+    let first_line_end = markdown.find('\n')?;
+    if markdown[..first_line_end].trim_end() != delimiter {
+        return None;
+    }
+
+    let start = first_line_end + 1;
+    let mut offset = start;
+    for line in markdown[start..].split_inclusive('\n') {
+        if line.trim_end() == delimiter {
+            return Some(FrontMatter {
+                format,
+                contents: &markdown[start..offset],
+                range: 0..offset + line.len(),
+            });
+        }
+        offset += line.len();
+    }
+
+    None
+}
+
+fn from_yaml(value: serde_yaml::Value) -> Result<Option<MetaValue>> {
+    use serde_yaml::Value;
+    Ok(Some(match value {
+        Value::Null => return Ok(None),
+        Value::Bool(value) => MetaValue::Bool(value),
+        Value::Number(number) => match number.as_i64() {
+            Some(value) => MetaValue::Integer(value),
+            None => MetaValue::Text(number.to_string()),
+        },
+        Value::String(text) => MetaValue::Text(text),
+        Value::Sequence(values) => MetaValue::List(
+            values
+                .into_iter()
+                .filter_map(|value| from_yaml(value).transpose())
+                .collect::<Result<_>>()?,
+        ),
+        Value::Mapping(_) => return Err(anyhow!("nested mappings are not supported")),
+        Value::Tagged(tagged) => return from_yaml(tagged.value),
+    }))
+}
+
+fn from_toml(value: toml::Value) -> Result<MetaValue> {
+    use toml::Value;
+    Ok(match value {
+        Value::String(text) => MetaValue::Text(text),
+        Value::Integer(value) => MetaValue::Integer(value),
+        Value::Float(value) => MetaValue::Text(value.to_string()),
+        Value::Boolean(value) => MetaValue::Bool(value),
+        Value::Datetime(datetime) => MetaValue::Text(datetime.to_string()),
+        Value::Array(values) => {
+            MetaValue::List(values.into_iter().map(from_toml).collect::<Result<_>>()?)
+        }
+        Value::Table(_) => return Err(anyhow!("nested tables are not supported")),
+    })
+}
+
+/// Parse the key-value pairs of the front matter, in the order they were written
+/// (or sorted by key, for TOML). Keys with no value are returned with `None`.
+pub(crate) fn parse(front_matter: &FrontMatter) -> Result<Vec<(String, Option<MetaValue>)>> {
+    match front_matter.format {
+        Format::Yaml => {
+            let value: serde_yaml::Value =
+                serde_yaml::from_str(front_matter.contents).context("Invalid YAML front matter")?;
+            let mapping = match value {
+                serde_yaml::Value::Null => return Ok(vec![]),
+                serde_yaml::Value::Mapping(mapping) => mapping,
+                _ => {
+                    return Err(anyhow!(
+                        "YAML front matter must be a mapping of keys to values"
+                    ))
+                }
+            };
+            mapping
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = match key {
+                        serde_yaml::Value::String(key) => key,
+                        key => return Err(anyhow!("Front matter key {:?} is not text", key)),
+                    };
+                    let value = from_yaml(value).context(format!("Invalid value for {:?}", key))?;
+                    Ok((key, value))
+                })
+                .collect()
+        }
+        Format::Toml => {
+            let table: toml::value::Table =
+                toml::from_str(front_matter.contents).context("Invalid TOML front matter")?;
+            table
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = from_toml(value).context(format!("Invalid value for {:?}", key))?;
+                    Ok((key, Some(value)))
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn front_matter_found() {
+        let markdown = "---\ntitle: Hi\n---\n# Post";
+        assert_eq!(
+            find(markdown),
+            Some(FrontMatter {
+                format: Format::Yaml,
+                contents: "title: Hi\n",
+                range: 0..18,
+            })
+        );
+
+        let markdown = "+++\r\ntitle = \"Hi\"\r\n+++\r\n";
+        let front_matter = find(markdown).unwrap();
+        assert_eq!(front_matter.format, Format::Toml);
+        assert_eq!(front_matter.contents, "title = \"Hi\"\r\n");
+        assert_eq!(front_matter.range, 0..markdown.len());
+    }
+
+    #[test]
+    fn key_lines_found() {
+        let yaml = find("---\ntitle: Hi\n\"draft\" : true\n---\n").unwrap();
+        assert_eq!(yaml.line_of("title"), 2);
+        assert_eq!(yaml.line_of("draft"), 3);
+        assert_eq!(yaml.line_of("tags"), 1);
+
+        let toml = find("+++\ntitles = 1\ntitle = \"Hi\"\n+++\n").unwrap();
+        assert_eq!(toml.line_of("title"), 3);
+    }
+
+    #[test]
+    fn front_matter_not_found() {
+        assert_eq!(find("# Post\n---\n"), None);
+        assert_eq!(find("---\ntitle: Never closed\n"), None);
+        assert_eq!(find("----\n\n----\n"), None);
+        assert_eq!(find("---"), None);
+    }
+
+    #[test]
+    fn typed_values_parsed() {
+        let yaml =
+            find("---\ndraft: true\ntags: [a, b]\npart: 2\ncreated: 2020-02-20\nempty:\n---\n")
+                .unwrap();
+        assert_eq!(
+            parse(&yaml).unwrap(),
+            vec![
+                ("draft".to_string(), Some(MetaValue::Bool(true))),
+                (
+                    "tags".to_string(),
+                    Some(MetaValue::List(vec![
+                        MetaValue::Text("a".into()),
+                        MetaValue::Text("b".into())
+                    ]))
+                ),
+                ("part".to_string(), Some(MetaValue::Integer(2))),
+                (
+                    "created".to_string(),
+                    Some(MetaValue::Text("2020-02-20".into()))
+                ),
+                ("empty".to_string(), None),
+            ]
+        );
+
+        let toml = find("+++\ndraft = false\ncreated = 2020-02-20T10:00:00Z\n+++\n").unwrap();
+        assert_eq!(
+            parse(&toml).unwrap(),
+            vec![
+                (
+                    "created".to_string(),
+                    Some(MetaValue::Text("2020-02-20T10:00:00Z".into()))
+                ),
+                ("draft".to_string(), Some(MetaValue::Bool(false))),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_front_matter_is_error() {
+        assert!(parse(&find("---\n- not a mapping\n---\n").unwrap()).is_err());
+        assert!(parse(&find("---\nnested:\n  key: value\n---\n").unwrap()).is_err());
+        assert!(parse(&find("+++\nnot toml\n+++\n").unwrap()).is_err());
+    }
+
+    #[test]
+    fn values_converted() {
+        assert_eq!(
+            MetaValue::Text(" A title ".into()).as_text(),
+            Some("A title".to_string())
+        );
+        assert_eq!(MetaValue::Integer(2).as_text(), Some("2".to_string()));
+        assert_eq!(MetaValue::List(vec![]).as_text(), None);
+
+        assert_eq!(MetaValue::Bool(true).as_bool(), Some(true));
+        assert_eq!(MetaValue::Text(" False".into()).as_bool(), Some(false));
+        assert_eq!(MetaValue::Integer(1).as_bool(), None);
+
+        assert_eq!(
+            MetaValue::Text("rust, web,".into()).as_list(),
+            Some(vec!["rust".to_string(), "web".to_string()])
+        );
+        assert_eq!(
+            MetaValue::List(vec![MetaValue::Text("rust".into()), MetaValue::Integer(2)]).as_list(),
+            Some(vec!["rust".to_string(), "2".to_string()])
+        );
+        assert_eq!(
+            MetaValue::List(vec![MetaValue::List(vec![])]).as_list(),
+            None
+        );
+    }
+}
@@ -1,8 +1,60 @@
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Context, Result};
 
+use crate::images::optimize_image;
+
+/// Extensions of the files worth compressing ahead of time, which are those made of text.
+const COMPRESSIBLE_EXTENSIONS: [&str; 4] = ["html", "css", "xml", "txt"];
+
+/// A format in which files can be compressed, so that servers can send them as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    /// All of the supported formats.
+    pub const ALL: [Encoding; 2] = [Encoding::Gzip, Encoding::Brotli];
+
+    /// The extension added to the name of the compressed files.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gz",
+            Encoding::Brotli => "br",
+        }
+    }
+
+    /// The name of the format, as used in the `Content-Encoding` header.
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+        }
+    }
+
+    /// Compress the data with the maximum compression level of the format.
+    pub fn compress(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Encoding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            Encoding::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 11, 22);
+                encoder.write_all(data)?;
+                Ok(encoder.into_inner())
+            }
+        }
+    }
+}
+
+/// A single change to the filesystem, planned before being performed.
 #[derive(Debug)]
 pub enum FsAction {
     Copy {
@@ -18,15 +70,39 @@ pub enum FsAction {
         path: PathBuf,
         exists_ok: bool,
     },
+    DeleteFile {
+        path: PathBuf,
+        not_exists_ok: bool,
+    },
 
     /// Creates file if it does not exist, overwrites if it does exist.
     WriteFile {
         path: PathBuf,
         content: String,
     },
+
+    /// Writes a compressed copy of a file, which must exist by the time the action is
+    /// performed. If compressing the file would not make it smaller, any previous copy is
+    /// deleted instead.
+    Compress {
+        source: PathBuf,
+        dest: PathBuf,
+        encoding: Encoding,
+    },
+
+    /// Writes a copy of a JPEG or PNG image no wider than `max_width`, encoded with the given
+    /// quality, along with a WebP version of a PNG if `webp` is true and that makes it smaller.
+    OptimizeImage {
+        source: PathBuf,
+        dest: PathBuf,
+        max_width: u32,
+        quality: u8,
+        webp: bool,
+    },
 }
 use FsAction::*;
 
+/// Perform all the actions in order, stopping at the first one that fails.
 pub fn execute_fs_actions(actions: &[FsAction]) -> Result<()> {
     // This code is full of checks which are followed by actions, non-atomically.
     // This means that it's full of TOCTOU race conditions. I don't know how to avoid that.
@@ -70,22 +146,433 @@ pub fn execute_fs_actions(actions: &[FsAction]) -> Result<()> {
                             path
                         ));
                     }
-                    return Ok(());
+                    continue;
                 }
                 fs::create_dir(path).context(format!("Could not create directory '{:?}'", path))?;
             }
+            DeleteFile {
+                path,
+                not_exists_ok,
+            } => {
+                if !path.exists() {
+                    if !not_exists_ok {
+                        return Err(anyhow!(
+                            "File '{:?}' could not be deleted because it does not exist",
+                            path
+                        ));
+                    }
+                    continue;
+                }
+                fs::remove_file(path).context(format!("Could not delete file '{:?}'", path))?;
+            }
             WriteFile { path, content } => {
                 if path.exists() && !path.is_file() {
                     return Err(anyhow!(
-                        "Could not write file '{:?}': a directory already exists"
+                        "Could not write file '{:?}': a directory already exists",
+                        path
                     ));
                 }
 
                 // fs::write handles creation and truncation for us.
                 fs::write(path, content).context(format!("Could not write file '{:?}'", path))?;
             }
+            Compress {
+                source,
+                dest,
+                encoding,
+            } => {
+                let data =
+                    fs::read(source).context(format!("Could not read file '{:?}'", source))?;
+                let compressed = encoding
+                    .compress(&data)
+                    .context(format!("Could not compress file '{:?}'", source))?;
+                if compressed.len() < data.len() {
+                    fs::write(dest, compressed)
+                        .context(format!("Could not write file '{:?}'", dest))?;
+                } else if dest.is_file() {
+                    // Otherwise, servers would keep sending an outdated version of the file.
+                    fs::remove_file(dest).context(format!("Could not delete file '{:?}'", dest))?;
+                }
+            }
+            OptimizeImage {
+                source,
+                dest,
+                max_width,
+                quality,
+                webp,
+            } => {
+                optimize_image(source, dest, *max_width, *quality, *webp)
+                    .context(format!("Could not optimize image '{:?}'", source))?;
+            }
         }
     }
 
     Ok(())
 }
+
+/// Add the actions to write a compressed copy in every format of the text files written by
+/// the given actions, next to each of them, and to delete the copies of the files deleted.
+pub fn add_compress_actions(actions: &mut Vec<FsAction>) {
+    let mut with_compressed = Vec::with_capacity(actions.len());
+    for action in actions.drain(..) {
+        let (path, deleted) = match &action {
+            Copy { dest: path, .. } | WriteFile { path, .. } => (path.clone(), false),
+            DeleteFile { path, .. } => (path.clone(), true),
+            _ => {
+                with_compressed.push(action);
+                continue;
+            }
+        };
+        with_compressed.push(action);
+        if !is_compressible(&path) {
+            continue;
+        }
+
+        for &encoding in Encoding::ALL.iter() {
+            let mut dest = path.clone().into_os_string();
+            dest.push(".");
+            dest.push(encoding.extension());
+            with_compressed.push(if deleted {
+                DeleteFile {
+                    path: dest.into(),
+                    not_exists_ok: true,
+                }
+            } else {
+                Compress {
+                    source: path.clone(),
+                    dest: dest.into(),
+                    encoding,
+                }
+            });
+        }
+    }
+    *actions = with_compressed;
+}
+
+/// Whether the file is made of text, which compresses well.
+fn is_compressible(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| COMPRESSIBLE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+impl FsAction {
+    /// The amount of bytes that this action will write, if any and if known.
+    pub fn size(&self) -> Option<u64> {
+        match self {
+            Copy { source, .. } => fs::metadata(source).ok().map(|m| m.len()),
+            WriteFile { content, .. } => Some(content.len() as u64),
+            // Not known until the file is compressed.
+            DeleteDir { .. }
+            | CreateDir { .. }
+            | DeleteFile { .. }
+            | Compress { .. }
+            | OptimizeImage { .. } => None,
+        }
+    }
+
+    /// Describe what the action will do, without performing it.
+    pub fn describe(&self) -> String {
+        let size = match (self, self.size()) {
+            (_, Some(size)) => format!(" ({} bytes)", size),
+            (Copy { .. }, None) => " (size unknown)".to_string(),
+            (_, None) => String::new(),
+        };
+        match self {
+            Copy { source, dest } => format!(
+                "copy         {} -> {}{}",
+                source.to_string_lossy(),
+                dest.to_string_lossy(),
+                size
+            ),
+            DeleteDir {
+                path,
+                not_exists_ok,
+                recursive,
+            } => format!(
+                "delete dir   {}{}{}",
+                path.to_string_lossy(),
+                if *recursive { " (recursive)" } else { "" },
+                if *not_exists_ok {
+                    " (if it exists)"
+                } else {
+                    ""
+                },
+            ),
+            CreateDir { path, exists_ok } => format!(
+                "create dir   {}{}",
+                path.to_string_lossy(),
+                if *exists_ok {
+                    " (unless it exists)"
+                } else {
+                    ""
+                },
+            ),
+            DeleteFile {
+                path,
+                not_exists_ok,
+            } => format!(
+                "delete file  {}{}",
+                path.to_string_lossy(),
+                if *not_exists_ok {
+                    " (if it exists)"
+                } else {
+                    ""
+                },
+            ),
+            WriteFile { path, .. } => format!("write file   {}{}", path.to_string_lossy(), size),
+            Compress {
+                source,
+                dest,
+                encoding,
+            } => format!(
+                "compress     {} -> {} ({})",
+                source.to_string_lossy(),
+                dest.to_string_lossy(),
+                encoding.name()
+            ),
+            OptimizeImage {
+                source,
+                dest,
+                max_width,
+                quality,
+                webp,
+            } => format!(
+                "optimize     {} -> {} (up to {}px wide, quality {}{})",
+                source.to_string_lossy(),
+                dest.to_string_lossy(),
+                max_width,
+                quality,
+                if *webp { ", and as WebP" } else { "" }
+            ),
+        }
+    }
+
+    /// Machine-readable representation of the action, without the contents of the files.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut value = match self {
+            Copy { source, dest } => serde_json::json!({
+                "action": "copy",
+                "source": source,
+                "dest": dest,
+            }),
+            DeleteDir {
+                path,
+                not_exists_ok,
+                recursive,
+            } => serde_json::json!({
+                "action": "delete_dir",
+                "path": path,
+                "not_exists_ok": not_exists_ok,
+                "recursive": recursive,
+            }),
+            CreateDir { path, exists_ok } => serde_json::json!({
+                "action": "create_dir",
+                "path": path,
+                "exists_ok": exists_ok,
+            }),
+            DeleteFile {
+                path,
+                not_exists_ok,
+            } => serde_json::json!({
+                "action": "delete_file",
+                "path": path,
+                "not_exists_ok": not_exists_ok,
+            }),
+            WriteFile { path, .. } => serde_json::json!({
+                "action": "write_file",
+                "path": path,
+            }),
+            Compress {
+                source,
+                dest,
+                encoding,
+            } => serde_json::json!({
+                "action": "compress",
+                "source": source,
+                "dest": dest,
+                "encoding": encoding.name(),
+            }),
+            OptimizeImage {
+                source,
+                dest,
+                max_width,
+                quality,
+                webp,
+            } => serde_json::json!({
+                "action": "optimize_image",
+                "source": source,
+                "dest": dest,
+                "max_width": max_width,
+                "quality": quality,
+                "webp": webp,
+            }),
+        };
+        if let Copy { .. } | WriteFile { .. } = self {
+            value["bytes"] = self.size().into();
+        }
+        value
+    }
+}
+
+/// Describe all the actions that would be performed, one per line, followed by a summary.
+pub fn describe_fs_actions(actions: &[FsAction]) -> String {
+    let mut plan = String::new();
+    for action in actions {
+        plan.push_str(&action.describe());
+        plan.push('\n');
+    }
+    let total: u64 = actions.iter().filter_map(FsAction::size).sum();
+    plan.push_str(&format!(
+        "{} actions, {} bytes to be written\n",
+        actions.len(),
+        total
+    ));
+    plan
+}
+
+/// Represent all the actions that would be performed as a JSON array.
+pub fn fs_actions_to_json(actions: &[FsAction]) -> String {
+    let plan: Vec<_> = actions.iter().map(FsAction::to_json).collect();
+    serde_json::to_string_pretty(&plan).expect("Serializing JSON in-memory failed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan() -> Vec<FsAction> {
+        vec![
+            DeleteDir {
+                path: "dist/post".into(),
+                not_exists_ok: true,
+                recursive: true,
+            },
+            CreateDir {
+                path: "dist/post".into(),
+                exists_ok: false,
+            },
+            WriteFile {
+                path: "dist/post/index.html".into(),
+                content: "<p>Hi</p>".into(),
+            },
+            Copy {
+                source: "content/post/missing.png".into(),
+                dest: "dist/post/missing.png".into(),
+            },
+        ]
+    }
+
+    #[test]
+    fn plan_described() {
+        let description = describe_fs_actions(&plan());
+        let lines: Vec<_> = description.lines().collect();
+
+        assert_eq!(
+            lines,
+            [
+                "delete dir   dist/post (recursive) (if it exists)",
+                "create dir   dist/post",
+                "write file   dist/post/index.html (9 bytes)",
+                "copy         content/post/missing.png -> dist/post/missing.png (size unknown)",
+                "4 actions, 9 bytes to be written",
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_as_json() {
+        let json: serde_json::Value = serde_json::from_str(&fs_actions_to_json(&plan())).unwrap();
+
+        assert_eq!(json.as_array().unwrap().len(), 4);
+        assert_eq!(json[0]["action"], "delete_dir");
+        assert_eq!(json[0]["recursive"], true);
+        assert_eq!(json[2]["action"], "write_file");
+        assert_eq!(json[2]["path"], "dist/post/index.html");
+        assert_eq!(json[2]["bytes"], 9);
+        assert!(json[2].get("content").is_none());
+        assert_eq!(json[3]["bytes"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn text_files_compressed() -> Result<()> {
+        let mut actions = plan();
+        actions.push(WriteFile {
+            path: "dist/atom.XML".into(),
+            content: String::new(),
+        });
+        actions.push(DeleteFile {
+            path: "dist/post/notes.txt".into(),
+            not_exists_ok: true,
+        });
+        add_compress_actions(&mut actions);
+        let description = describe_fs_actions(&actions);
+        let lines: Vec<_> = description.lines().collect();
+
+        assert_eq!(
+            lines,
+            [
+                "delete dir   dist/post (recursive) (if it exists)",
+                "create dir   dist/post",
+                "write file   dist/post/index.html (9 bytes)",
+                "compress     dist/post/index.html -> dist/post/index.html.gz (gzip)",
+                "compress     dist/post/index.html -> dist/post/index.html.br (br)",
+                "copy         content/post/missing.png -> dist/post/missing.png (size unknown)",
+                "write file   dist/atom.XML (0 bytes)",
+                "compress     dist/atom.XML -> dist/atom.XML.gz (gzip)",
+                "compress     dist/atom.XML -> dist/atom.XML.br (br)",
+                "delete file  dist/post/notes.txt (if it exists)",
+                "delete file  dist/post/notes.txt.gz (if it exists)",
+                "delete file  dist/post/notes.txt.br (if it exists)",
+                "12 actions, 9 bytes to be written",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_files_written_only_if_smaller() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("pagong-compress-{}", std::process::id()));
+        let text = "<p>Hello, world!</p>\n".repeat(100);
+        let mut actions = vec![
+            CreateDir {
+                path: dir.clone(),
+                exists_ok: true,
+            },
+            WriteFile {
+                path: dir.join("big.html"),
+                content: text.clone(),
+            },
+            WriteFile {
+                path: dir.join("tiny.html"),
+                content: "a".into(),
+            },
+        ];
+        add_compress_actions(&mut actions);
+        // A leftover from a previous build, when the file was bigger.
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("tiny.html.gz"), "stale")?;
+
+        let result = execute_fs_actions(&actions);
+        let exists = |name: &str| dir.join(name).exists();
+        let (big_gz, big_br, tiny_gz, tiny_br) = (
+            fs::read(dir.join("big.html.gz")),
+            fs::read(dir.join("big.html.br")),
+            exists("tiny.html.gz"),
+            exists("tiny.html.br"),
+        );
+        fs::remove_dir_all(&dir)?;
+        result?;
+
+        let mut decoded = String::new();
+        std::io::Read::read_to_string(
+            &mut flate2::read::GzDecoder::new(big_gz?.as_slice()),
+            &mut decoded,
+        )?;
+        assert_eq!(decoded, text);
+        assert!(big_br?.len() < text.len());
+        assert!(!tiny_gz);
+        assert!(!tiny_br);
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+//! Dates of files according to the history of the git repository containing them.
+
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, FixedOffset};
+
+/// Find the dates of the first and last commits that touched the given file or directory.
+///
+/// Returns `None` if no commit touched it because it's not tracked yet, and an error if it's
+/// tracked but its history is missing (for example, in a shallow clone).
+pub(crate) fn commit_dates(
+    path: &Path,
+) -> Result<Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)>> {
+    // Run git from the file's directory, so that it finds the repository containing it
+    // no matter where pagong runs.
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Path '{:?}' does not name a file", path))?;
+
+    let mut command = Command::new("git");
+    command.arg("log").arg("--format=%aI");
+    // Only the history of a single file can be followed across renames, which would otherwise
+    // make its creation date that of the last rename.
+    if path.is_file() {
+        command.arg("--follow");
+    }
+    let output = command
+        .arg("--")
+        .arg(name)
+        .current_dir(dir)
+        .output()
+        .context("Could not run git")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git log failed for '{:?}': {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let stdout = String::from_utf8(output.stdout).context("git printed invalid UTF-8")?;
+    let dates = stdout
+        .lines()
+        .map(|line| {
+            DateTime::parse_from_rfc3339(line)
+                .context(format!("git printed an invalid date {:?}", line))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // Commits are listed in the order of the history, but their author dates may be in any
+    // order (for example, after a rebase), so the earliest and latest ones are taken.
+    match (dates.iter().min(), dates.iter().max()) {
+        (Some(first), Some(last)) => Ok(Some((*first, *last))),
+        _ if is_tracked(dir, name)? => Err(anyhow!(
+            "git has no commits of '{:?}' although it's tracked, maybe because it's only \
+             staged or the repository is a shallow clone",
+            path
+        )),
+        _ => Ok(None),
+    }
+}
+/// Whether git tracks the file or directory with the given name, inside `dir`.
+fn is_tracked(dir: &Path, name: &OsStr) -> Result<bool> {
+    let output = Command::new("git")
+        .arg("ls-files")
+        .arg("--")
+        .arg(name)
+        .current_dir(dir)
+        .output()
+        .context("Could not run git")?;
+    Ok(output.status.success() && !output.stdout.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    /// Run git in `dir` with a fixed author and date, failing if it does.
+    fn git(dir: &Path, date: &str, args: &[&str]) -> Result<()> {
+        let status = Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(["-c", "commit.gpgsign=false"])
+            .args(args)
+            .env("GIT_AUTHOR_DATE", date)
+            .env("GIT_COMMITTER_DATE", date)
+            .current_dir(dir)
+            .status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(anyhow!("git {:?} failed", args))
+        }
+    }
+
+    #[test]
+    fn commit_dates_found() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("pagong-git-{}", std::process::id()));
+        fs::create_dir_all(dir.join("post"))?;
+        let post = dir.join("post/index.md");
+        let result = (|| -> Result<_> {
+            git(&dir, "2020-02-20T10:00:00+01:00", &["init", "--quiet"])?;
+            fs::write(&post, "# First")?;
+            git(&dir, "2020-02-20T10:00:00+01:00", &["add", "."])?;
+            git(
+                &dir,
+                "2020-02-20T10:00:00+01:00",
+                &["commit", "--quiet", "-m", "1"],
+            )?;
+            fs::write(&post, "# Second")?;
+            git(
+                &dir,
+                "2021-03-03T12:30:00+02:00",
+                &["commit", "--quiet", "-am", "2"],
+            )?;
+            // Committed later, but authored earlier, as after a rebase.
+            fs::write(&post, "# Third")?;
+            git(
+                &dir,
+                "2019-12-01T08:00:00+01:00",
+                &["commit", "--quiet", "-am", "3"],
+            )?;
+            git(&dir, "2022-01-01T00:00:00+00:00", &["mv", "post", "moved"])?;
+            git(
+                &dir,
+                "2022-01-01T00:00:00+00:00",
+                &["commit", "--quiet", "-m", "4"],
+            )?;
+            fs::write(dir.join("staged.md"), "# Staged")?;
+            git(&dir, "2021-03-03T12:30:00+02:00", &["add", "staged.md"])?;
+            fs::write(dir.join("untracked.md"), "# Untracked")?;
+
+            let dates = |name: &str| commit_dates(&dir.join(name));
+            Ok((
+                dates("moved")?,
+                dates("moved/index.md")?,
+                dates("untracked.md")?,
+                dates("staged.md").is_err(),
+            ))
+        })();
+        fs::remove_dir_all(&dir)?;
+        let (post_dir, post_file, untracked, staged_failed) = result?;
+
+        let first = DateTime::parse_from_rfc3339("2019-12-01T08:00:00+01:00")?;
+        let last = DateTime::parse_from_rfc3339("2022-01-01T00:00:00+00:00")?;
+        // Only the history of files is followed across renames.
+        assert_eq!(post_dir, Some((last, last)));
+        assert_eq!(post_file, Some((first, last)));
+        assert_eq!(post_file.unwrap().0.offset().local_minus_utc(), 3600);
+        assert_eq!(untracked, None);
+        assert!(staged_failed);
+        Ok(())
+    }
+}
@@ -23,7 +23,7 @@
 
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::{Arguments, Write as FmtWrite};
-use std::io::{self, ErrorKind, Write};
+use std::io::{self, ErrorKind};
 
 use crate::escape::{escape_href, escape_html};
 
@@ -35,36 +35,15 @@ enum TableState {
     Body,
 }
 
-/// This wrapper exists because we can't have both a blanket implementation
-/// for all types implementing `Write` and types of the for `&mut W` where
-/// `W: StrWrite`. Since we need the latter a lot, we choose to wrap
-/// `Write` types.
-struct WriteWrapper<W>(W);
-
 /// Trait that allows writing string slices. This is basically an extension
 /// of `std::io::Write` in order to include `String`.
-pub(crate) trait StrWrite {
+pub trait StrWrite {
     fn write_str(&mut self, s: &str) -> io::Result<()>;
 
     fn write_fmt(&mut self, args: Arguments) -> io::Result<()>;
 }
 
-impl<W> StrWrite for WriteWrapper<W>
-where
-    W: Write,
-{
-    #[inline]
-    fn write_str(&mut self, s: &str) -> io::Result<()> {
-        self.0.write_all(s.as_bytes())
-    }
-
-    #[inline]
-    fn write_fmt(&mut self, args: Arguments) -> io::Result<()> {
-        self.0.write_fmt(args)
-    }
-}
-
-impl<'w> StrWrite for String {
+impl StrWrite for String {
     #[inline]
     fn write_str(&mut self, s: &str) -> io::Result<()> {
         self.push_str(s);
@@ -98,7 +77,7 @@ struct ImageParagraphFilter<I: Iterator> {
     queue: VecDeque<I::Item>,
 }
 
-impl<'a, I: Iterator> ImageParagraphFilter<I> {
+impl<I: Iterator> ImageParagraphFilter<I> {
     fn new(iter: I) -> Self {
         Self {
             iter,
@@ -133,7 +112,7 @@ where
 
         let buffer = [
             first.as_ref(),
-            self.queue.get(0),
+            self.queue.front(),
             self.queue.get(1),
             self.queue.get(2),
             self.queue.get(3),
@@ -242,10 +221,10 @@ where
             }
         }
 
-        panic!(format!(
+        panic!(
             "user somehow wrote {} identically-named headings",
             usize::MAX - 2
-        ));
+        );
     }
 
     /// Writes a new line.
@@ -495,7 +474,7 @@ where
                 } else {
                     self.write("\n<p class=\"footnote\" id=\"f.")?;
                 }
-                escape_html(&mut self.writer, &*name)?;
+                escape_html(&mut self.writer, &name)?;
                 self.write("\"><sup>")?;
                 let len = self.numbers.len() + 1;
                 let number = *self.numbers.entry(name).or_insert(len);
@@ -609,10 +588,14 @@ where
 /// Iterate over an `Iterator` of `Event`s, generate HTML for each `Event`, and
 /// push it to a `String`.
 ///
+/// Unlike `pulldown_cmark`'s renderer, the first heading is treated as the title,
+/// every heading gets an anchor, and standalone images get a caption.
+///
 /// # Examples
 ///
 /// ```
-/// use pulldown_cmark::{html, Parser};
+/// use pagong::html;
+/// use pulldown_cmark::Parser;
 ///
 /// let markdown_str = r#"
 /// hello
@@ -626,12 +609,12 @@ where
 /// let mut html_buf = String::new();
 /// html::push_html(&mut html_buf, parser);
 ///
-/// assert_eq!(html_buf, r#"<h1>hello</h1>
+/// assert_eq!(html_buf, r##"<h1 class="title" id="hello"><a class="anchor" href="#hello">¶</a>hello</h1>
 /// <ul>
 /// <li>alpha</li>
 /// <li>beta</li>
 /// </ul>
-/// "#);
+/// "##);
 /// ```
 pub fn push_html<'a, I>(s: &mut String, iter: I)
 where
@@ -661,7 +644,11 @@ Some text below.";
 
         assert!(matches!(
             image_paragraph,
-            [(Start(Tag::Image(..)), true), (Text(_), false), (End(Tag::Image(..)), false)]
+            [
+                (Start(Tag::Image(..)), true),
+                (Text(_), false),
+                (End(Tag::Image(..)), false)
+            ]
         ));
     }
 
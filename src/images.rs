@@ -0,0 +1,238 @@
+//! Optimization of the images of posts, so that readers don't download more pixels than
+//! their screens can show.
+
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType as ResizeFilter;
+use image::{DynamicImage, ImageDecoder, ImageReader};
+use pulldown_cmark::{CowStr, Event, Tag};
+
+use crate::markup::percent_decode;
+
+/// The format of an image that can be optimized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ImageFormat {
+    Jpeg,
+    Png,
+}
+
+impl ImageFormat {
+    /// The format of the image at the given path judging by its extension, if it's one of
+    /// the formats that can be optimized.
+    pub(crate) fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_ref() {
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "png" => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+}
+
+/// The name of the unmodified copy of an image, which is only written if a post links to it.
+pub(crate) fn original_name(name: &str) -> String {
+    match name.rsplit_once('.') {
+        Some((stem, extension)) => format!("{}.original.{}", stem, extension),
+        None => format!("{}.original", name),
+    }
+}
+
+/// The name of the WebP version of an image, which servers can send instead to the browsers
+/// that support it.
+pub(crate) fn webp_name(name: &str) -> String {
+    format!("{}.webp", name)
+}
+
+/// The asset a URL inside a post points to, if any.
+pub(crate) fn find_asset<'a>(assets: &'a [PathBuf], url: &str) -> Option<&'a PathBuf> {
+    let url = percent_decode(url.trim_start_matches("./"));
+    assets
+        .iter()
+        .find(|asset| asset.file_name().is_some_and(|name| *name == *url))
+}
+
+/// Point the links to the optimized images of a post to their original version instead, so
+/// that readers can still get the full image.
+pub(crate) fn rewrite_links<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+    assets: &'a [PathBuf],
+) -> impl Iterator<Item = Event<'a>> {
+    events.map(move |event| match event {
+        Event::Start(Tag::Link(kind, url, title)) => {
+            let optimized = find_asset(assets, &url)
+                .filter(|asset| ImageFormat::from_path(asset).is_some())
+                .and_then(|asset| asset.file_name());
+            let url = match optimized {
+                Some(name) => CowStr::from(original_name(&name.to_string_lossy())),
+                None => url,
+            };
+            Event::Start(Tag::Link(kind, url, title))
+        }
+        event => event,
+    })
+}
+
+/// Write an optimized version of the JPEG or PNG image at `source` to `dest`, no wider than
+/// `max_width` pixels and encoded with the given quality (from 1 to 100) if it's a JPEG. If
+/// `webp` is true and the image is a PNG, a lossless WebP version is written too, named after
+/// `dest`. Photos rarely get smaller without losses, so JPEGs never get one.
+///
+/// Neither version is ever bigger than the original: if encoding the image again would not
+/// make it smaller, it's copied as is instead, and the WebP version is only written if it's
+/// smaller than the other (otherwise, any previous one is deleted). Performs I/O.
+pub(crate) fn optimize_image(
+    source: &Path,
+    dest: &Path,
+    max_width: u32,
+    quality: u8,
+    webp: bool,
+) -> Result<()> {
+    let format = ImageFormat::from_path(source)
+        .context(format!("Image '{:?}' is not a JPEG or PNG", source))?;
+    let original = fs::read(source).context(format!("Could not read image '{:?}'", source))?;
+    let mut decoder = ImageReader::new(Cursor::new(&original))
+        .with_guessed_format()
+        .context(format!("Could not read image '{:?}'", source))?
+        .into_decoder()
+        .context(format!("Could not read image '{:?}'", source))?;
+    // The orientation is lost when the image is encoded again, so it's applied to the pixels.
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)
+        .context(format!("Could not decode image '{:?}'", source))?;
+    image.apply_orientation(orientation);
+
+    if image.width() > max_width {
+        image = image.resize(max_width, image.height(), ResizeFilter::Lanczos3);
+    }
+
+    let mut encoded = Vec::new();
+    match format {
+        ImageFormat::Jpeg => DynamicImage::from(image.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut encoded, quality)),
+        ImageFormat::Png => image.write_with_encoder(PngEncoder::new_with_quality(
+            &mut encoded,
+            CompressionType::Best,
+            FilterType::Adaptive,
+        )),
+    }
+    .context(format!("Could not encode image '{:?}'", source))?;
+    let optimized = if encoded.len() < original.len() {
+        encoded
+    } else {
+        original
+    };
+    fs::write(dest, &optimized).context(format!("Could not write image '{:?}'", dest))?;
+
+    if !webp || format != ImageFormat::Png {
+        return Ok(());
+    }
+    let mut webp_dest = dest.as_os_str().to_owned();
+    webp_dest.push(".webp");
+    let webp_dest = PathBuf::from(webp_dest);
+
+    // The encoder can't take the 16-bit images PNG can have.
+    let mut encoded = Vec::new();
+    if image.color().has_alpha() {
+        DynamicImage::from(image.to_rgba8())
+            .write_with_encoder(WebPEncoder::new_lossless(&mut encoded))
+    } else {
+        DynamicImage::from(image.to_rgb8())
+            .write_with_encoder(WebPEncoder::new_lossless(&mut encoded))
+    }
+    .context(format!("Could not encode image '{:?}' as WebP", source))?;
+    if encoded.len() < optimized.len() {
+        fs::write(&webp_dest, encoded)
+            .context(format!("Could not write image '{:?}'", webp_dest))?;
+    } else if webp_dest.is_file() {
+        fs::remove_file(&webp_dest).context(format!("Could not delete image '{:?}'", webp_dest))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{GenericImageView, RgbImage};
+    use pulldown_cmark::Parser;
+
+    #[test]
+    fn names_derived() {
+        assert_eq!(original_name("cat.jpg"), "cat.original.jpg");
+        assert_eq!(original_name("my.cat.PNG"), "my.cat.original.PNG");
+        assert_eq!(webp_name("my.cat.png"), "my.cat.png.webp");
+        assert_eq!(
+            ImageFormat::from_path(Path::new("a/b.JPEG")),
+            Some(ImageFormat::Jpeg)
+        );
+        assert_eq!(ImageFormat::from_path(Path::new("a/b.gif")), None);
+    }
+
+    #[test]
+    fn links_to_optimized_images_rewritten() {
+        let assets = vec![
+            PathBuf::from("post/cat.jpg"),
+            PathBuf::from("post/a chart.png"),
+            PathBuf::from("post/notes.txt"),
+        ];
+        let markdown = "![Cat](cat.jpg) [full size](./cat.jpg) [chart](a%20chart.png) \
+                        [notes](notes.txt) [elsewhere](https://example.com/cat.jpg)";
+        let mut html = String::new();
+        crate::html::push_html(&mut html, rewrite_links(Parser::new(markdown), &assets));
+
+        assert_eq!(
+            html,
+            "<p><img src=\"cat.jpg\" alt=\"Cat\" /> \
+             <a href=\"cat.original.jpg\">full size</a> \
+             <a href=\"a%20chart.original.png\">chart</a> \
+             <a href=\"notes.txt\">notes</a> \
+             <a href=\"https://example.com/cat.jpg\">elsewhere</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn images_resized_and_encoded() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("pagong-images-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        // Noise, which compresses much worse without losses.
+        let photo = dir.join("photo.jpg");
+        let mut seed = 1u32;
+        RgbImage::from_fn(200, 100, |_, _| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let [r, g, b, _] = seed.to_be_bytes();
+            image::Rgb([r, g, b])
+        })
+        .save(&photo)?;
+        let drawing = dir.join("drawing.png");
+        RgbImage::from_pixel(40, 20, image::Rgb([255, 255, 255])).save(&drawing)?;
+        // A leftover from a previous build.
+        fs::write(dir.join("same.png.webp"), "stale")?;
+
+        let result = (|| -> Result<_> {
+            optimize_image(&photo, &dir.join("small.jpg"), 100, 80, true)?;
+            optimize_image(&drawing, &dir.join("same.png"), 100, 80, true)?;
+            Ok((
+                image::open(dir.join("small.jpg"))?,
+                dir.join("small.jpg.webp").exists(),
+                image::open(dir.join("same.png"))?,
+                fs::metadata(dir.join("same.png"))?.len() <= fs::metadata(&drawing)?.len(),
+                image::open(dir.join("same.png.webp"))?,
+            ))
+        })();
+        fs::remove_dir_all(&dir)?;
+        let (small, small_webp, same, not_bigger, same_webp) = result?;
+
+        assert_eq!(small.dimensions(), (100, 50));
+        assert!(!small_webp);
+        assert_eq!(same.dimensions(), (40, 20));
+        assert!(not_bigger);
+        assert_eq!(same_webp.dimensions(), (40, 20));
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+//! A static site generator for slow connections.
+//!
+//! This crate contains everything the `pagong` command-line tool uses, so that blogs can
+//! also be generated from other programs. Generating a site happens in three steps:
+//!
+//! 1. Load a [`Blog`] with all its [`Post`]s from a content directory.
+//! 2. Plan the changes to the output directory as a list of [`FsAction`]s.
+//! 3. Execute those actions with [`execute_fs_actions`].
+//!
+//! [`Blog::generate`] performs the last two steps at once.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use pagong::{Blog, Config};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let config = Config::from_file("pagong.toml")?;
+//! let blog = Blog::from_source_dir("content", config)?;
+//!
+//! // Review the plan before touching the disk.
+//! let actions = blog.generate_actions("dist")?;
+//! print!("{}", pagong::describe_fs_actions(&actions));
+//!
+//! pagong::execute_fs_actions(&actions)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Individual posts can also be rendered without writing anything:
+//!
+//! ```
+//! use pagong::{Blog, Config, Post};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let post = Post::from_markdown("hello.md", "# Hello\n\nWorld.".into());
+//! let blog = Blog::new(Config::default(), vec![post]);
+//!
+//! let html = blog.render_post(&blog.posts[0])?;
+//! assert!(html.contains("<title>Hello</title>"));
+//! # Ok(())
+//! # }
+//! ```
+
+mod blog;
+mod config;
+mod css;
+pub mod escape;
+mod front_matter;
+mod fs_action;
+mod git;
+pub mod html;
+mod images;
+mod markup;
+mod post;
+pub mod serve;
+mod template;
+pub mod watch;
+mod weight;
+
+pub use blog::{Blog, PostChange};
+pub use config::Config;
+pub use fs_action::{
+    add_compress_actions, describe_fs_actions, execute_fs_actions, fs_actions_to_json, Encoding,
+    FsAction,
+};
+pub use post::{MetaWarning, Post};
+pub use template::Templates;
+pub use weight::{page_weights, PageWeight};
+
+/// Directory containing the blog's sources, relative to where `pagong` runs.
+pub const DEFAULT_CONTENT_PATH: &str = "content";
+/// Directory where the generated site is written, relative to where `pagong` runs.
+pub const DEFAULT_OUTPUT_PATH: &str = "dist";
+/// Port on localhost where `pagong serve` listens.
+pub const DEFAULT_SERVE_PORT: u16 = 8000;
+/// Name of the configuration file, which lives next to the content directory.
+pub const CONFIG_FILE_NAME: &str = "pagong.toml";
+/// Markdown included at the top of every post.
+pub const HEADER_FILE_NAME: &str = "header.md";
+/// Markdown included at the bottom of every post.
+pub const FOOTER_FILE_NAME: &str = "footer.md";
+/// Name of the markdown file inside a post's directory.
+pub const FOLDER_POST_NAME: &str = "post.md";
+/// Name of the stylesheet, both in the content and the output directory.
+pub const CSS_FILE_NAME: &str = "style.css";
+/// Directory inside the output directory where the stylesheet is written.
+pub const CSS_DIR_NAME: &str = "css";
+/// Directory inside the output directory with the list of tags and a page for each tag.
+pub const TAGS_DIR_NAME: &str = "tags";
+/// Directory inside the output directory with an overview page for each series of posts.
+pub const SERIES_DIR_NAME: &str = "series";
+/// Directory inside the content directory with templates that replace the built-in ones.
+pub const TEMPLATES_DIR_NAME: &str = "templates";
+/// Directory inside the content directory or a theme with files which are copied as-is to
+/// the directory of the same name inside the output directory.
+pub const STATIC_DIR_NAME: &str = "static";
@@ -1,34 +1,113 @@
-mod blog;
-mod escape;
-mod fs_action;
-mod html;
-mod post;
+mod cli;
 
 use std::fs;
-use std::path::Path;
+use std::io::{self, Write};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 use anyhow::{anyhow, Context, Result};
+use clap::Parser;
 
-pub use blog::Blog;
-pub use post::Post;
+use pagong::serve::{self, Server};
+use pagong::watch::{ChangeWatcher, Rebuild};
+use pagong::{
+    describe_fs_actions, execute_fs_actions, fs_actions_to_json, page_weights, Blog, Config,
+    FsAction, Post, PostChange,
+};
 
-pub const DEFAULT_CONTENT_PATH: &str = "content";
-pub const HEADER_FILE_NAME: &str = "header.md";
-pub const FOOTER_FILE_NAME: &str = "footer.md";
-pub const FOLDER_POST_NAME: &str = "post.md";
-pub const CSS_FILE_NAME: &str = "style.css";
-pub const CSS_DIR_NAME: &str = "css";
+use cli::{BuildArgs, BuildCommandArgs, Cli, Command, PlanFormat, ServeArgs};
 
-fn main() -> Result<()> {
-    let blog = Blog::from_source_dir(DEFAULT_CONTENT_PATH)?;
+/// The stage at which a command failed, which determines the process' exit code.
+///
+/// Exit code 2 is reserved for invalid command-line usage, and is used by the argument parser.
+#[derive(Debug)]
+enum Failure {
+    /// The configuration file could not be loaded or was invalid.
+    Config(anyhow::Error),
+    /// The content directory or one of its posts could not be loaded.
+    Content(anyhow::Error),
+    /// The site could not be generated or written to the output directory.
+    Output(anyhow::Error),
+    /// Some of the pages of the site are heavier than allowed, so it was not generated.
+    Weight(anyhow::Error),
+}
+
+impl Failure {
+    fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            Failure::Config(_) => 3,
+            Failure::Content(_) => 4,
+            Failure::Output(_) => 5,
+            Failure::Weight(_) => 6,
+        })
+    }
+
+    fn error(&self) -> &anyhow::Error {
+        match self {
+            Failure::Config(e) | Failure::Content(e) | Failure::Output(e) | Failure::Weight(e) => e,
+        }
+    }
+}
+
+fn load_config(args: &BuildArgs) -> Result<Config> {
+    let (config_path, explicit) = args.config_path();
+    if explicit || config_path.exists() {
+        Config::from_file(&config_path)
+    } else {
+        log::warn!(
+            "Configuration file \"{}\" not found, using the default configuration",
+            config_path.to_string_lossy()
+        );
+        Ok(Config::default())
+    }
+}
+
+/// Report the problems found in the metadata of the posts, which are errors in strict mode.
+fn check_metadata<'a>(posts: impl IntoIterator<Item = &'a Post>, strict: bool) -> Result<()> {
+    let warnings: Vec<_> = posts
+        .into_iter()
+        .flat_map(|post| post.warnings.iter())
+        .collect();
+    if !strict {
+        for warning in warnings {
+            log::warn!("{}, ignoring it", warning);
+        }
+        return Ok(());
+    }
 
-    let output_dir = Path::new("dist");
+    if warnings.is_empty() {
+        Ok(())
+    } else {
+        let list: Vec<_> = warnings.iter().map(|w| w.to_string()).collect();
+        Err(anyhow!(
+            "Found {} problem(s) in the metadata of posts:\n{}",
+            list.len(),
+            list.join("\n")
+        ))
+    }
+}
+
+fn load_blog(args: &BuildArgs, config: Config) -> Result<Blog> {
+    let mut blog = Blog::from_source_dir(&args.content, config)?;
+    check_metadata(&blog.posts, args.strict)?;
+    blog.drafts = args.drafts;
+    blog.future = args.future;
+    log::debug!(
+        "Loaded {} posts from \"{}\"",
+        blog.posts.len(),
+        args.content.to_string_lossy()
+    );
+    Ok(blog)
+}
+
+fn prepare_output_dir(output_dir: &Path) -> Result<()> {
     if !output_dir.exists() {
-        eprintln!(
+        log::info!(
             "Creating output directory \"{}\"...",
             output_dir.to_string_lossy()
         );
-        fs::create_dir(output_dir).context("Could not create output directory")?;
+        fs::create_dir_all(output_dir).context("Could not create output directory")?;
     }
 
     // TODO consider using FsAction here for DRY
@@ -39,7 +118,314 @@ fn main() -> Result<()> {
         ));
     }
 
-    blog.generate(output_dir)?;
+    Ok(())
+}
+
+/// Format an amount of bytes as kilobytes, which is the unit of the page weight budget.
+fn kilobytes(bytes: u64) -> String {
+    format!("{:.1} KB", bytes as f64 / 1000.0)
+}
+
+/// Log the weight of every page that the actions would write, from heaviest to lightest, and
+/// check that no post is heavier than `max_weight` kilobytes.
+fn check_page_weights(
+    blog: &Blog,
+    output_dir: &Path,
+    actions: &[FsAction],
+    max_weight: Option<u64>,
+) -> Result<()> {
+    let weights = page_weights(output_dir, actions);
+    if weights.is_empty() {
+        return Ok(());
+    }
 
+    log::info!("Weight of the {} pages, heaviest first:", weights.len());
+    for weight in weights.iter() {
+        log::info!(
+            "{:>10}  {} ({} of HTML and {} of {} other file(s))",
+            kilobytes(weight.total()),
+            weight.path.to_string_lossy(),
+            kilobytes(weight.html),
+            kilobytes(weight.total() - weight.html),
+            weight.resources.len()
+        );
+        for (path, size) in weight.resources.iter() {
+            log::debug!("{:>10}  {}", kilobytes(*size), path.to_string_lossy());
+        }
+    }
+
+    let max_weight = match max_weight {
+        Some(max_weight) => max_weight,
+        None => return Ok(()),
+    };
+    let post_pages: Vec<_> = blog
+        .posts
+        .iter()
+        .filter(|post| blog.is_published(post))
+        .map(|post| output_dir.join(&post.path).join("index.html"))
+        .collect();
+    let too_heavy: Vec<_> = weights
+        .iter()
+        .filter(|weight| post_pages.contains(&weight.path) && weight.total() > max_weight * 1000)
+        .map(|weight| {
+            format!(
+                "{} ({})",
+                weight.path.to_string_lossy(),
+                kilobytes(weight.total())
+            )
+        })
+        .collect();
+    if too_heavy.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Found {} post(s) heavier than the maximum of {} KB:\n{}",
+            too_heavy.len(),
+            max_weight,
+            too_heavy.join("\n")
+        ))
+    }
+}
+
+/// Load the whole site, returning it along with the actions which would generate it.
+fn plan_blog(args: &BuildArgs) -> Result<(Blog, Vec<FsAction>), Failure> {
+    let config = load_config(args).map_err(Failure::Config)?;
+    let blog = load_blog(args, config).map_err(Failure::Content)?;
+    let actions = blog
+        .generate_actions(args.output_dir())
+        .context("Could not generate all blog information")
+        .map_err(Failure::Output)?;
+    Ok((blog, actions))
+}
+
+/// Generate the whole site by executing the planned actions.
+fn write_blog(args: &BuildArgs, actions: &[FsAction]) -> Result<(), Failure> {
+    let output_dir = args.output_dir();
+    prepare_output_dir(&output_dir).map_err(Failure::Output)?;
+    execute_fs_actions(actions).map_err(Failure::Output)?;
+    log::info!("Site generated in \"{}\"", output_dir.to_string_lossy());
     Ok(())
 }
+
+/// Load and generate the whole site, returning the blog that was generated.
+fn build_blog(args: &BuildArgs) -> Result<Blog, Failure> {
+    let (blog, actions) = plan_blog(args)?;
+    write_blog(args, &actions)?;
+    Ok(blog)
+}
+
+fn build(args: &BuildCommandArgs) -> Result<(), Failure> {
+    let (blog, actions) = plan_blog(&args.build)?;
+    if !args.dry_run {
+        // The weights are checked first, so that a site which is too heavy is not written.
+        check_page_weights(
+            &blog,
+            &args.build.output_dir(),
+            &actions,
+            args.max_page_weight,
+        )
+        .map_err(Failure::Weight)?;
+        return write_blog(&args.build, &actions);
+    }
+
+    let plan = match args.format {
+        PlanFormat::Text => describe_fs_actions(&actions),
+        PlanFormat::Json => fs_actions_to_json(&actions) + "\n",
+    };
+    io::stdout()
+        .write_all(plan.as_bytes())
+        .context("Could not print the planned actions")
+        .map_err(Failure::Output)
+}
+
+/// The sources of the posts linked from the navigation of the post with the given source.
+fn adjacent_sources(blog: &Blog, source: &Path) -> Vec<PathBuf> {
+    match blog.posts.iter().find(|post| post.source == source) {
+        Some(post) => {
+            let (previous, next) = blog.adjacent_posts(post);
+            previous
+                .into_iter()
+                .chain(next)
+                .map(|post| post.source.clone())
+                .collect()
+        }
+        None => Vec::new(),
+    }
+}
+
+/// Update the site after only some posts changed, writing as little as possible.
+fn rebuild_posts(blog: &mut Blog, args: &BuildArgs, changes: &[PostChange]) -> Result<bool> {
+    // The neighbours of a post link to it, so their pages are written again too, both
+    // those from before the change and after it, in case the post moved.
+    let mut neighbours: Vec<_> = changes
+        .iter()
+        .flat_map(|change| adjacent_sources(blog, &change.source))
+        .collect();
+
+    for change in changes {
+        let old = blog.reload_post(&change.source)?;
+        let post = blog
+            .posts
+            .iter()
+            .find(|post| post.source == change.source)
+            .expect("Reloaded post should still exist");
+        check_metadata(Some(post), args.strict)?;
+        if post.path != old.path
+            || post.aliases != old.aliases
+            || post.unlisted != old.unlisted
+            || blog.is_published(post) != blog.is_published(&old)
+        {
+            // The post's directory or redirects would be left behind or the post would be
+            // listed in the wrong places, so everything needs to be cleaned.
+            return Ok(false);
+        }
+        if post.series.is_some() || old.series.is_some() {
+            // The other parts of the series link to the post, so they need to be written too.
+            return Ok(false);
+        }
+    }
+
+    neighbours.extend(
+        changes
+            .iter()
+            .flat_map(|change| adjacent_sources(blog, &change.source)),
+    );
+    let mut changes = changes.to_vec();
+    for source in neighbours {
+        if !changes.iter().any(|change| change.source == source) {
+            changes.push(PostChange {
+                source,
+                assets: Vec::new(),
+            });
+        }
+    }
+
+    let actions = blog
+        .generate_incremental_actions(args.output_dir(), &changes)
+        .context("Could not generate all blog information")?;
+    execute_fs_actions(&actions)?;
+    Ok(true)
+}
+
+fn watch(args: &BuildArgs) -> Result<(), Failure> {
+    let (config_path, _) = args.config_path();
+
+    let mut blog = build_blog(args)?;
+
+    let watcher = ChangeWatcher::new(&args.content, &config_path, blog.config.theme.as_deref())
+        .map_err(Failure::Content)?;
+    log::info!(
+        "Watching \"{}\" for changes (press Ctrl+C to stop)",
+        args.content.to_string_lossy()
+    );
+
+    loop {
+        let changed = watcher.wait().map_err(Failure::Content)?;
+        for path in changed.iter() {
+            log::debug!("Changed: {}", path.to_string_lossy());
+        }
+
+        let rebuilt = match watcher.classify(&blog, &changed) {
+            Rebuild::Nothing => continue,
+            Rebuild::Posts(changes) => match rebuild_posts(&mut blog, args, &changes) {
+                Ok(true) => {
+                    log::info!("Updated {} post(s)", changes.len());
+                    Ok(())
+                }
+                Ok(false) => Err(()),
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    Err(())
+                }
+            },
+            Rebuild::Everything => Err(()),
+        };
+
+        // Fall back to a full build, which also makes sure the site is left consistent.
+        if rebuilt.is_err() {
+            match build_blog(args) {
+                Ok(new_blog) => blog = new_blog,
+                // Keep the last good state so that the writer can fix the problem.
+                Err(failure) => log::error!("{:?}", failure.error()),
+            }
+        }
+    }
+}
+
+/// Build the site for local previewing into `output_dir`, with links pointing to the server,
+/// returning the blog that was generated.
+fn build_for_preview(
+    args: &ServeArgs,
+    addr: SocketAddr,
+    output_dir: &Path,
+) -> Result<Blog, Failure> {
+    let mut config = load_config(&args.build).map_err(Failure::Config)?;
+    config.base_url = format!("http://{}", addr);
+    let blog = load_blog(&args.build, config).map_err(Failure::Content)?;
+
+    let mut actions = blog
+        .generate_actions(output_dir)
+        .context("Could not generate all blog information")
+        .map_err(Failure::Output)?;
+    serve::inject_live_reload(&mut actions);
+    execute_fs_actions(&actions).map_err(Failure::Output)?;
+    Ok(blog)
+}
+
+fn serve(args: &ServeArgs) -> Result<(), Failure> {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, args.port));
+    let (config_path, _) = args.build.config_path();
+    let output_dir = args.output_dir();
+
+    prepare_output_dir(&output_dir).map_err(Failure::Output)?;
+    let blog = build_for_preview(args, addr, &output_dir)?;
+
+    let watcher = ChangeWatcher::new(
+        &args.build.content,
+        &config_path,
+        blog.config.theme.as_deref(),
+    )
+    .map_err(Failure::Content)?;
+    let server = Server::start(addr, output_dir.clone()).map_err(Failure::Output)?;
+    log::info!(
+        "Serving \"{}\" at http://{}/ (press Ctrl+C to stop)",
+        output_dir.to_string_lossy(),
+        addr
+    );
+
+    loop {
+        let changed = watcher.wait().map_err(Failure::Content)?;
+        for path in changed.iter() {
+            log::debug!("Changed: {}", path.to_string_lossy());
+        }
+
+        match build_for_preview(args, addr, &output_dir) {
+            Ok(_) => {
+                log::info!("Rebuilt after {} changed file(s)", changed.len());
+                server.reload();
+            }
+            // Keep serving the last good build so that the writer can fix the problem.
+            Err(failure) => log::error!("{:?}", failure.error()),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    cli::init_logger(cli.level_filter());
+
+    let result = match &cli.command {
+        Some(Command::Build(args)) => build(args),
+        Some(Command::Watch(args)) => watch(args),
+        Some(Command::Serve(args)) => serve(args),
+        None => build(&BuildCommandArgs::default()),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            log::error!("{:?}", failure.error());
+            failure.exit_code()
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Reading back the HTML of the generated pages, to find out what they contain.
+
+/// An opening tag found in a page.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Tag<'a> {
+    /// The name of the tag, in lowercase.
+    pub name: String,
+    /// The attributes of the tag with their names in lowercase, and their values as written
+    /// (empty if the attribute has no value).
+    pub attributes: Vec<(String, &'a str)>,
+}
+
+impl<'a> Tag<'a> {
+    /// The value of the attribute with the given name, if the tag has it.
+    pub fn attribute(&self, name: &str) -> Option<&'a str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|&(_, value)| value)
+    }
+}
+
+/// Find all the opening tags of the given HTML, in order.
+///
+/// This is not a full HTML parser, but it's enough for the pages `pagong` generates, whose
+/// text never contains unescaped `<`.
+pub(crate) fn tags(html: &str) -> Vec<Tag<'_>> {
+    let mut tags = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let name_len = rest
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+            .unwrap_or(rest.len());
+        if name_len == 0 {
+            // A closing tag, a comment or a doctype.
+            continue;
+        }
+        let mut tag = Tag {
+            name: rest[..name_len].to_ascii_lowercase(),
+            attributes: Vec::new(),
+        };
+        rest = &rest[name_len..];
+
+        // Read the attributes until the end of the tag.
+        loop {
+            rest = rest.trim_start();
+            let name_len = rest
+                .find(|c: char| c.is_whitespace() || matches!(c, '=' | '>' | '/'))
+                .unwrap_or(rest.len());
+            let name = rest[..name_len].to_ascii_lowercase();
+            rest = rest[name_len..].trim_start();
+            if name.is_empty() {
+                match rest.chars().next() {
+                    Some('/') => {
+                        rest = &rest[1..];
+                        continue;
+                    }
+                    _ => break,
+                }
+            }
+
+            let value = match rest.strip_prefix('=') {
+                Some(after) => {
+                    let after = after.trim_start();
+                    let (value, remaining) = match after.chars().next() {
+                        Some(quote @ ('"' | '\'')) => {
+                            let end = after[1..].find(quote).map_or(after.len(), |i| i + 1);
+                            (&after[1..end], after.get(end + 1..).unwrap_or(""))
+                        }
+                        _ => {
+                            let end = after
+                                .find(|c: char| c.is_whitespace() || c == '>')
+                                .unwrap_or(after.len());
+                            (&after[..end], &after[end..])
+                        }
+                    };
+                    rest = remaining;
+                    value
+                }
+                None => "",
+            };
+            tag.attributes.push((name, value));
+        }
+        tags.push(tag);
+    }
+    tags
+}
+
+/// Undo the escaping of characters in a URL, such as `%20` for spaces.
+pub(crate) fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_found() {
+        let tags = tags(
+            "<!DOCTYPE html>\n<HTML lang=\"en\"><body>\n<!-- a comment -->\n\
+             <h1 class=\"title  big\" id='top'>Hi</h1><img src=x.png alt=\"a > b\"/>\
+             <input disabled class=wide></body></html>",
+        );
+        let names: Vec<_> = tags.iter().map(|tag| tag.name.as_str()).collect();
+        assert_eq!(names, ["html", "body", "h1", "img", "input"]);
+        assert_eq!(
+            tags[3].attributes,
+            [("src".to_string(), "x.png"), ("alt".to_string(), "a > b")]
+        );
+        assert_eq!(tags[2].attribute("id"), Some("top"));
+        assert_eq!(tags[4].attribute("disabled"), Some(""));
+        assert_eq!(tags[4].attribute("class"), Some("wide"));
+        assert_eq!(tags[4].attribute("id"), None);
+    }
+}
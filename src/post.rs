@@ -1,111 +1,296 @@
-use crate::{html, FOLDER_POST_NAME};
+use crate::front_matter::{self, MetaValue};
+use crate::{git, html, images, Config, FOLDER_POST_NAME, TAGS_DIR_NAME};
 
 use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
 use std::ffi::{OsStr, OsString};
+use std::fmt;
 use std::fs;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
-use chrono::offset::Local;
-use chrono::{Date, NaiveDate, TimeZone};
+use chrono::offset::{Local, Offset, Utc};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
 
 use anyhow::{Context, Result};
 
+/// A single blog entry, written in markdown.
 #[derive(Debug, Clone)]
 pub struct Post {
+    /// The standalone file or directory the post was loaded from
+    pub source: PathBuf,
     pub markdown: String,
     /// The name that will become part of the post's URL
     pub path: OsString,
+    /// The title as plain text, for places where markup is not allowed
     pub title: String,
-    pub modified: Date<Local>,
-    pub created: Date<Local>,
+    /// The title with its inline formatting as HTML, without links
+    pub title_html: String,
+    /// Short description of the post, which overrides the summary found in its text
+    pub description: Option<String>,
+    pub modified: DateTime<FixedOffset>,
+    pub created: DateTime<FixedOffset>,
+    /// Files that will be copied next to the post
     pub assets: Vec<PathBuf>,
+    /// Names of the tags the post is listed under, in the order they were written
+    pub tags: Vec<String>,
+    /// Whether the post is a work in progress, which is only generated on request
+    pub draft: bool,
+    /// Whether the post is left out of the index page, tag pages and feed, so that it
+    /// can only be reached by those who know its URL
+    pub unlisted: bool,
+    /// Previous paths of the post, relative to the root of the site, which redirect to it
+    pub aliases: Vec<String>,
+    /// Name of the series of posts this one is a part of, if any
+    pub series: Option<String>,
+    /// Position of the post in its series. Parts without one go last, by creation date
+    pub series_part: Option<u32>,
+    /// Problems found in the post's metadata, which was ignored where invalid
+    pub warnings: Vec<MetaWarning>,
 }
 
 #[derive(Debug)]
 struct Metadata {
+    /// The title as inline markdown.
     title: Option<String>,
+    description: Option<String>,
     path: OsString,
-    created: Date<Local>,
-    modified: Date<Local>,
+    created: DateTime<FixedOffset>,
+    modified: DateTime<FixedOffset>,
+    /// The time zone of dates written without an offset, or the local one if `None`.
+    timezone: Option<Tz>,
+    tags: Vec<String>,
+    draft: bool,
+    unlisted: bool,
+    aliases: Vec<String>,
+    series: Option<String>,
+    series_part: Option<u32>,
 }
 
+/// A problem found in the metadata of a post, such as an unknown key or an invalid value.
+///
+/// The metadata with problems is ignored, unless the site is built in strict mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetaWarning {
+    /// The markdown file containing the metadata
+    pub file: PathBuf,
+    /// The line of the file with the problem, starting at 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for MetaWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file.display(), self.line, self.message)
+    }
+}
+
+/// A key and its value (if any) found in the metadata, along with its line in the post.
+type MetaPair = (usize, String, Option<MetaValue>);
+
+/// The line number (starting at 1) of the byte at `offset`.
+fn line_at(contents: &str, offset: usize) -> usize {
+    contents[..offset].matches('\n').count() + 1
+}
+
+const DATE_FORMAT_REASON: &str =
+    "the format was not YYYY-mm-dd, optionally followed by THH:MM:SS and an offset";
+
 impl Metadata {
-    fn update_from_contents(&mut self, contents: &str) -> Option<Range<usize>> {
-        let mut parser = Parser::new(contents).into_offset_iter();
+    /// Apply the metadata found at the start of the post, returning the range of the
+    /// contents to remove. Problems found are added to `warnings` along with their line.
+    fn update_from_contents(
+        &mut self,
+        contents: &str,
+        warnings: &mut Vec<(usize, String)>,
+    ) -> Option<Range<usize>> {
         let mut remove_range = None;
 
-        let first = parser.next();
-        if let Some((Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))), start_range)) =
-            first
-        {
-            if lang.as_ref() == "meta" {
-                remove_range = Some(start_range.clone());
-                self.update_from_meta_contents(&contents[start_range]);
+        if let Some(front_matter) = front_matter::find(contents) {
+            remove_range = Some(front_matter.range.clone());
+            match front_matter::parse(&front_matter) {
+                Ok(pairs) => {
+                    let pairs = pairs
+                        .into_iter()
+                        .map(|(key, value)| (front_matter.line_of(&key), key, value))
+                        .collect();
+                    self.update_from_pairs(pairs, warnings);
+                }
+                Err(e) => warnings.push((1, format!("Invalid front matter: {:#}", e))),
+            }
+        } else {
+            let mut parser = Parser::new(contents).into_offset_iter();
+            let first = parser.next();
+            if let Some((Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))), start_range)) =
+                first
+            {
+                if lang.as_ref() == "meta" {
+                    remove_range = Some(start_range.clone());
+                    let first_line = line_at(contents, start_range.start);
+                    self.update_from_meta_contents(&contents[start_range], first_line, warnings);
+                }
             }
         }
 
         if self.title.is_none() {
-            // Extract first header as title
-            let mut wait_title = false;
-            for event in Parser::new(&contents) {
+            // Extract the inline markdown of the first header as title
+            let body_start = remove_range.as_ref().map_or(0, |range| range.end);
+            let body = &contents[body_start..];
+            let mut inside_title = false;
+            let mut title_range: Option<Range<usize>> = None;
+            for (event, range) in Parser::new(body).into_offset_iter() {
                 match event {
-                    Event::Start(Tag::Heading(1)) => wait_title = true,
-                    Event::Text(s) if wait_title => {
-                        self.title = Some(s.to_string());
-                        break;
+                    Event::Start(Tag::Heading(1)) => inside_title = true,
+                    Event::End(Tag::Heading(1)) if inside_title => break,
+                    _ if inside_title => {
+                        title_range = Some(match title_range {
+                            Some(title_range) => title_range.start..range.end,
+                            None => range,
+                        })
                     }
                     _ => {}
                 }
             }
+            self.title = title_range.map(|range| body[range].to_string());
         }
 
         remove_range
     }
 
-    fn update_from_meta_contents(&mut self, contents: &str) {
-        for line in contents
+    /// Apply the `key: value` lines of a meta block starting at line `first_line`.
+    fn update_from_meta_contents(
+        &mut self,
+        contents: &str,
+        first_line: usize,
+        warnings: &mut Vec<(usize, String)>,
+    ) {
+        let mut pairs = Vec::new();
+        for (i, line) in contents
             .split('\n')
+            .enumerate()
             .skip(1) // ```meta
-            .filter(|line| !line.is_empty() && line.trim() != "```")
+            .filter(|(_, line)| !line.is_empty() && line.trim() != "```")
         {
             let mut kv = line.splitn(2, ':');
             let key = kv.next().unwrap();
-            let value = if let Some(v) = kv.next() {
-                v
-            } else {
-                eprintln!("Ignoring meta override line {:?} in post {:?} because it does not have a value", line, self.path);
+            let value = kv.next().map(|v| MetaValue::Text(v.to_string()));
+            if value.is_none() {
+                warnings.push((
+                    first_line + i,
+                    format!("Meta line {:?} does not have a value", line),
+                ));
                 continue;
+            }
+            pairs.push((first_line + i, key.to_string(), value));
+        }
+        self.update_from_pairs(pairs, warnings);
+    }
+
+    fn update_from_pairs(&mut self, pairs: Vec<MetaPair>, warnings: &mut Vec<(usize, String)>) {
+        for (line, key, value) in pairs {
+            let result = match value {
+                Some(value) => self.apply(&key, &value),
+                None => Err(format!("Meta key {:?} does not have a value", key)),
             };
+            if let Err(message) = result {
+                warnings.push((line, message));
+            }
+        }
+    }
 
-            match key.to_lowercase().as_ref() {
-                "title" => self.title = Some(value.trim().to_string()),
-                "path" => self.path = value.trim().into(),
-                "created" | "published" => match parse_date(value) {
-                    Ok(date) => self.created = date,
-                    Err(_) => eprintln!("Invalid {:?} override value for {:?} in post {:?} because the format was not YYYY-mm-dd", value, key, self.path),
-                },
-                "modified" | "updated" => match parse_date(value) {
-                    Ok(date) => self.modified = date,
-                    Err(_) => eprintln!("Invalid {:?} override value for {:?} in post {:?} because the format was not YYYY-mm-dd", value, key, self.path),
-                },
-                _ => {
-                    eprintln!(
-                        "Unexpected meta override key {:?} in post {:?}, ignoring.",
-                        key,
-                        self.path
-                    );
+    /// Override the metadata for `key` with `value`, or explain why either is not valid.
+    fn apply(&mut self, key: &str, value: &MetaValue) -> Result<(), String> {
+        let key = key.trim();
+        let invalid = |reason: &str| {
+            Err(format!(
+                "Invalid value {:?} for meta key {:?} because {}",
+                value, key, reason
+            ))
+        };
+        match key.to_lowercase().as_ref() {
+            "title" => match value.as_text() {
+                Some(title) => self.title = Some(title),
+                None => return invalid("it is not text"),
+            },
+            "description" => match value.as_text() {
+                Some(description) => self.description = Some(description),
+                None => return invalid("it is not text"),
+            },
+            "path" => match value.as_text() {
+                Some(path) => self.path = path.into(),
+                None => return invalid("it is not text"),
+            },
+            "created" | "published" => match value.as_text().map(|v| parse_date(&v, self.timezone))
+            {
+                Some(Ok(date)) => self.created = date,
+                _ => return invalid(DATE_FORMAT_REASON),
+            },
+            "modified" | "updated" => {
+                match value.as_text().map(|v| parse_date(&v, self.timezone)) {
+                    Some(Ok(date)) => self.modified = date,
+                    _ => return invalid(DATE_FORMAT_REASON),
                 }
             }
+            "tags" => match value.as_list() {
+                Some(tags) => {
+                    self.tags.clear();
+                    for tag in tags {
+                        if !self.tags.contains(&tag) {
+                            self.tags.push(tag);
+                        }
+                    }
+                }
+                None => return invalid("it is not a list of text"),
+            },
+            "aliases" => match value.as_list() {
+                Some(aliases) => {
+                    let mut normalized = Vec::with_capacity(aliases.len());
+                    for alias in aliases {
+                        let alias = alias.trim_matches('/');
+                        if alias
+                            .split('/')
+                            .any(|part| part.is_empty() || part == "." || part == "..")
+                        {
+                            return invalid(
+                                "aliases must be paths without empty, '.' or '..' parts",
+                            );
+                        }
+                        normalized.push(alias.to_string());
+                    }
+                    self.aliases = normalized;
+                }
+                None => return invalid("it is not a list of text"),
+            },
+            "series" => match value.as_text() {
+                Some(series) if !series.is_empty() => self.series = Some(series),
+                _ => return invalid("it is not text"),
+            },
+            "series_part" => match value.as_text().and_then(|part| part.parse().ok()) {
+                Some(part) if part > 0 => self.series_part = Some(part),
+                _ => return invalid("it is not a whole number greater than zero"),
+            },
+            "draft" => match value.as_bool() {
+                Some(draft) => self.draft = draft,
+                None => return invalid("it is not true or false"),
+            },
+            "unlisted" => match value.as_bool() {
+                Some(unlisted) => self.unlisted = unlisted,
+                None => return invalid("it is not true or false"),
+            },
+            _ => return Err(format!("Unexpected meta key {:?}", key)),
         }
+        Ok(())
     }
 }
 
 impl Post {
     /// Construct a post from a standalone title.md or a title/ directory
     /// containing a post.md and optional assets. Performs I/O.
-    pub fn from_source_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+    ///
+    /// The configuration determines where the post's dates come from, and the time zone
+    /// of those written without an offset.
+    pub fn from_source_file<P: AsRef<Path>>(path: P, config: &Config) -> Result<Self> {
         let post_path = if path.as_ref().is_file() {
             path.as_ref().to_path_buf()
         } else if path.as_ref().is_dir() {
@@ -130,8 +315,27 @@ impl Post {
             .modified()
             .unwrap_or_else(|_| SystemTime::now());
 
-        let created = chrono::DateTime::from(created).date();
-        let modified = chrono::DateTime::from(modified).date();
+        let timezone = config.timezone;
+        let mut created = in_time_zone(DateTime::<Utc>::from(created), timezone);
+        let mut modified = in_time_zone(DateTime::<Utc>::from(modified), timezone);
+
+        if config.git_dates {
+            match git::commit_dates(path.as_ref()) {
+                Ok(Some((first, last))) => {
+                    created = in_time_zone(first, timezone);
+                    modified = in_time_zone(last, timezone);
+                }
+                Ok(None) => log::debug!(
+                    "Post {:?} has no commits yet, using the dates of its file",
+                    path.as_ref()
+                ),
+                Err(e) => log::warn!(
+                    "Could not find the commit dates of post {:?}, using the dates of its file: {:#}",
+                    path.as_ref(),
+                    e
+                ),
+            }
+        }
 
         let mut assets = vec![];
         let path = path.as_ref();
@@ -144,7 +348,7 @@ impl Post {
                     path
                 ))?;
 
-                if child.path().extension() != Some(&OsStr::new("md")) {
+                if child.path().extension() != Some(OsStr::new("md")) {
                     // don't add .md files as assets
                     assets.push(child.path());
                 }
@@ -152,19 +356,60 @@ impl Post {
         }
 
         Ok(Self::from_sources(
+            path.to_path_buf(),
             content,
             assets,
             Metadata {
                 title: None,
+                description: None,
                 path: path.file_stem().expect("Post file must have stem").into(),
                 modified,
                 created,
+                timezone,
+                tags: vec![],
+                draft: false,
+                unlisted: false,
+                aliases: vec![],
+                series: None,
+                series_part: None,
             },
         ))
     }
 
+    /// Construct a post from markdown which is already in memory, applying its meta overrides.
+    /// The post is considered to be created and modified now, unless overridden, and dates
+    /// without an offset are in the local time zone.
+    pub fn from_markdown<P: AsRef<Path>>(source: P, markdown: String) -> Self {
+        let source = source.as_ref();
+        let now = in_time_zone(Local::now(), None);
+        Self::from_sources(
+            source.to_path_buf(),
+            markdown,
+            vec![],
+            Metadata {
+                title: None,
+                description: None,
+                path: source.file_stem().unwrap_or_default().into(),
+                modified: now,
+                created: now,
+                timezone: None,
+                tags: vec![],
+                draft: false,
+                unlisted: false,
+                aliases: vec![],
+                series: None,
+                series_part: None,
+            },
+        )
+    }
+
     /// Partially parses markdown to apply meta overrides
-    fn from_sources(mut markdown: String, assets: Vec<PathBuf>, mut meta: Metadata) -> Self {
+    fn from_sources(
+        source: PathBuf,
+        mut markdown: String,
+        assets: Vec<PathBuf>,
+        mut meta: Metadata,
+    ) -> Self {
         // UTF-8 BOM becomes zero-width non-breaking space, which `trim()` won't remove,
         // but if we leave it there then metadata loading will break and not recognise
         // where the meta code block starts correctly.
@@ -174,36 +419,170 @@ impl Post {
             markdown = markdown.replace("\u{FEFF}", "");
         }
 
-        if let Some(remove_range) = meta.update_from_contents(&markdown) {
+        let mut warnings = Vec::new();
+        if let Some(remove_range) = meta.update_from_contents(&markdown, &mut warnings) {
             markdown.replace_range(remove_range, "");
         }
 
+        let file = if source.is_dir() {
+            source.join(FOLDER_POST_NAME)
+        } else {
+            source.clone()
+        };
+        let warnings = warnings
+            .into_iter()
+            .map(|(line, message)| MetaWarning {
+                file: file.clone(),
+                line,
+                message,
+            })
+            .collect();
+
         // Remove leading whitespace
         markdown = markdown.trim_start().into();
 
+        let (title, title_html) = meta
+            .title
+            .map(|title| render_inline(&title))
+            .filter(|(title, _)| !title.is_empty())
+            .unwrap_or_else(|| ("(no title)".to_string(), "(no title)".to_string()));
+
         Post {
+            source,
             markdown,
             path: meta.path,
-            title: meta.title.unwrap_or_else(|| "(no title)".to_string()),
+            title,
+            title_html,
+            description: meta.description,
             modified: meta.modified,
             created: meta.created,
             assets,
+            tags: meta.tags,
+            draft: meta.draft,
+            unlisted: meta.unlisted,
+            aliases: meta.aliases,
+            series: meta.series,
+            series_part: meta.series_part,
+            warnings,
         }
     }
 
-    pub fn generate_summary(&self) -> Option<String> {
-        let mut expecting_text = false;
-        Parser::new(&self.markdown).find_map(|e| match e {
-            Event::Start(Tag::Paragraph) => {
-                expecting_text = true;
-                None
+    /// Find the text to use as the summary of the post.
+    ///
+    /// This is its description if it has one, or else the text of the paragraphs before the
+    /// `<!-- more -->` marker. Without marker, the text of the first paragraph is used, cut
+    /// to be at most `max_length` characters long.
+    pub fn generate_summary(&self, max_length: usize) -> Option<String> {
+        if let Some(description) = &self.description {
+            return Some(description.clone());
+        }
+
+        let mut paragraphs = Vec::new();
+        let mut paragraph: Option<String> = None;
+        // The alternative text of images describes them, so it's not part of the summary.
+        let mut image_depth = 0;
+        for event in Parser::new_ext(&self.markdown, Options::all()) {
+            match event {
+                Event::Start(Tag::Paragraph) => paragraph = Some(String::new()),
+                Event::End(Tag::Paragraph) => paragraphs.extend(paragraph.take()),
+                Event::Start(Tag::Image(..)) => image_depth += 1,
+                Event::End(Tag::Image(..)) => image_depth -= 1,
+                Event::Text(text) | Event::Code(text) if image_depth == 0 => {
+                    if let Some(paragraph) = &mut paragraph {
+                        paragraph.push_str(&text);
+                    }
+                }
+                Event::SoftBreak | Event::HardBreak => {
+                    if let Some(paragraph) = &mut paragraph {
+                        paragraph.push(' ');
+                    }
+                }
+                Event::Html(html) if is_more_marker(&html) => {
+                    paragraphs.extend(paragraph.take());
+                    let excerpt = paragraphs
+                        .iter()
+                        .map(|text| collapse_whitespace(text))
+                        .filter(|text| !text.is_empty())
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    return Some(excerpt).filter(|excerpt| !excerpt.is_empty());
+                }
+                _ => {}
             }
-            Event::Text(text) if expecting_text => Some(text.to_string()),
-            _ => None,
-        })
+        }
+
+        paragraphs
+            .iter()
+            .map(|text| collapse_whitespace(text))
+            .find(|text| !text.is_empty())
+            .map(|text| truncate_words(&text, max_length))
+    }
+
+    /// Links to the page of each of the post's tags, or nothing if it has no tags.
+    fn tags_div(&self) -> String {
+        // Unlisted posts don't appear in the tag pages, so linking to them would be confusing.
+        if self.tags.is_empty() || self.unlisted {
+            return String::new();
+        }
+        let mut div = String::from("<div class=\"tags\">Tags: ");
+        for (i, tag) in self.tags.iter().enumerate() {
+            if i != 0 {
+                div.push_str(", ");
+            }
+            div.push_str(&format!("<a href=\"../{}/", TAGS_DIR_NAME));
+            crate::escape::escape_href(&mut div, &slugify(tag))
+                .expect("Should not fail to escape HREF in-memory");
+            div.push_str("/index.html\">");
+            crate::escape::escape_html(&mut div, tag)
+                .expect("Should not fail to escape HTML in-memory");
+            div.push_str("</a>");
+        }
+        div.push_str("</div>\n");
+        div
+    }
+
+    /// The assets the post links to, rather than only showing them as images.
+    pub(crate) fn linked_assets(&self) -> Vec<&PathBuf> {
+        Parser::new_ext(&self.markdown, Options::all())
+            .filter_map(|event| match event {
+                Event::Start(Tag::Link(_, url, _)) => images::find_asset(&self.assets, &url),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Render the post as HTML, surrounded by the given header and footer markdown.
+    ///
+    /// `intro_html` is inserted as-is after the title and dates. It must not contain blank
+    /// lines, or the rest of it would be read as markdown. If the site optimizes its images,
+    /// links to them point to their original version instead.
+    pub fn write_html(
+        &self,
+        config: &Config,
+        header: &str,
+        footer: &str,
+        intro_html: &str,
+        out: &mut String,
+    ) -> Result<()> {
+        let intro_html = self.tags_div() + intro_html;
+        self.write_html_with_intro(config, header, footer, &intro_html, out)
+    }
+
+    /// Render the post as HTML for the Atom feed, which leaves out the links to the tag pages
+    /// because they are relative to the post's page (and the feed has the tags already).
+    pub fn write_feed_html(&self, config: &Config, out: &mut String) -> Result<()> {
+        self.write_html_with_intro(config, "", "", "", out)
     }
 
-    pub fn write_html(&self, header: &str, footer: &str, out: &mut String) -> Result<()> {
+    /// Render the post as HTML, with `intro_html` inserted as-is after the title and dates.
+    fn write_html_with_intro(
+        &self,
+        config: &Config,
+        header: &str,
+        footer: &str,
+        intro_html: &str,
+        out: &mut String,
+    ) -> Result<()> {
         let date_format = "%Y-%m-%d";
         let create_date_div = format!(
             "<div class=\"date-created-modified\">{}</div>\n",
@@ -219,10 +598,11 @@ impl Post {
         let mut main = self.markdown[first_range.clone()].to_string()
             + "\n"
             + &create_date_div
+            + intro_html
             + "\n"
             + &self.markdown[first_range.end..];
 
-        if self.modified != self.created {
+        if self.modified.date() != self.created.date() {
             // This needs to start with a newline or it might get bundled with the previous
             // tag
             main.push_str(&format!(
@@ -234,27 +614,204 @@ Last edited {}</div>\n",
 
         let input = header.to_string() + "\n" + &main + "\n" + footer;
         let parser = Parser::new_ext(&input, options);
-        html::push_html(out, parser);
+        if config.optimize_images {
+            html::push_html(out, images::rewrite_links(parser, &self.assets));
+        } else {
+            html::push_html(out, parser);
+        }
         Ok(())
     }
 }
 
-/// Parse a string of the form YYYY-MM-DD into a "local" Date
-fn parse_date(date: &str) -> chrono::ParseResult<chrono::Date<Local>> {
+/// The name of the directory for the page of a tag or series. Names which only differ
+/// in case or punctuation share the same page.
+pub(crate) fn slugify(tag: &str) -> String {
+    let mut slug = String::with_capacity(tag.len());
+    for c in tag.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.truncate(slug.trim_end_matches('-').len());
+    if slug.is_empty() {
+        slug.push('-');
+    }
+    slug
+}
+
+/// Render inline markdown (such as a title) as plain text and as HTML.
+///
+/// Links are left out of the HTML, because titles are often made links themselves.
+fn render_inline(markdown: &str) -> (String, String) {
+    let events: Vec<_> = Parser::new_ext(markdown, Options::all())
+        .filter(|event| match event {
+            Event::Start(tag) | Event::End(tag) => !matches!(
+                tag,
+                Tag::Paragraph | Tag::Heading(_) | Tag::Link(..) | Tag::Image(..)
+            ),
+            _ => true,
+        })
+        .collect();
+
+    let mut plain = String::new();
+    for event in events.iter() {
+        match event {
+            Event::Text(text) | Event::Code(text) => plain.push_str(text),
+            Event::SoftBreak | Event::HardBreak => plain.push(' '),
+            _ => {}
+        }
+    }
+
+    let mut rendered = String::new();
+    html::push_html(&mut rendered, events.into_iter());
+    (collapse_whitespace(&plain), rendered.trim().to_string())
+}
+
+/// Whether the HTML is the `<!-- more -->` marker, which ends the excerpt of a post.
+fn is_more_marker(html: &str) -> bool {
+    html.trim()
+        .strip_prefix("<!--")
+        .and_then(|html| html.strip_suffix("-->"))
+        .is_some_and(|comment| comment.trim() == "more")
+}
+
+/// Replace all runs of whitespace with a single space, and remove it from both ends.
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Cut the text to at most `max_length` characters (plus an ellipsis), without
+/// splitting words unless a single word is longer than that.
+fn truncate_words(text: &str, max_length: usize) -> String {
+    let end = match text.char_indices().nth(max_length) {
+        Some((end, _)) => end,
+        None => return text.to_string(),
+    };
+    // The last word is kept if it ends right at the limit.
+    let cut = if text[end..].starts_with(char::is_whitespace) {
+        &text[..end]
+    } else {
+        match text[..end].rfind(char::is_whitespace) {
+            Some(space) if space > 0 => &text[..space],
+            _ => &text[..end],
+        }
+    };
+    let mut truncated = cut
+        .trim_end_matches(|c: char| c.is_whitespace() || c == ',')
+        .to_string();
+    truncated.push('…');
+    truncated
+}
+
+/// Convert a date and time to the given time zone (or the local one, if `None`),
+/// keeping only its offset.
+fn in_time_zone<Z: TimeZone>(datetime: DateTime<Z>, timezone: Option<Tz>) -> DateTime<FixedOffset> {
+    fn fix<Z: TimeZone>(datetime: DateTime<Z>) -> DateTime<FixedOffset> {
+        let offset = datetime.offset().fix();
+        datetime.with_timezone(&offset)
+    }
+    match timezone {
+        Some(tz) => fix(datetime.with_timezone(&tz)),
+        None => fix(datetime.with_timezone(&Local)),
+    }
+}
+
+/// Interpret a date and time without offset as being in the given time zone (or the
+/// local one, if `None`).
+fn from_local_datetime(naive: NaiveDateTime, timezone: Option<Tz>) -> DateTime<FixedOffset> {
+    fn resolve<Z: TimeZone>(zone: &Z, naive: NaiveDateTime) -> DateTime<FixedOffset> {
+        // Times skipped by a daylight saving change are moved forward, past the gap.
+        zone.from_local_datetime(&naive)
+            .earliest()
+            .or_else(|| {
+                zone.from_local_datetime(&(naive + Duration::hours(1)))
+                    .earliest()
+            })
+            .map(|datetime| in_time_zone(datetime, None))
+            .expect("There should always be a time after a daylight saving gap")
+    }
+    let datetime = match timezone {
+        Some(tz) => resolve(&tz, naive),
+        None => resolve(&Local, naive),
+    };
+    in_time_zone(datetime, timezone)
+}
+
+/// Parse a date of the form YYYY-MM-DD, optionally followed by a time and offset
+/// (such as 2020-02-20T10:00:00+02:00). Dates and times without an offset are
+/// assumed to be in the given time zone (or the local one, if `None`).
+fn parse_date(date: &str, timezone: Option<Tz>) -> chrono::ParseResult<DateTime<FixedOffset>> {
+    let date = date.trim();
     // ISO-format has priority
-    NaiveDate::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%z")
-        .or_else(|_| NaiveDate::parse_from_str(date, "%Y-%m-%d"))
-        .map(|date| {
-            TimeZone::from_local_date(&Local, &date)
-                .latest()
-                .expect("There should always be a latest date")
+    DateTime::parse_from_rfc3339(date)
+        .or_else(|_| DateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%z"))
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S")
+                .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S"))
+                .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M"))
+                .or_else(|_| {
+                    NaiveDate::parse_from_str(date, "%Y-%m-%d").map(|d| d.and_hms(0, 0, 0))
+                })
+                .map(|naive| from_local_datetime(naive, timezone))
         })
 }
 
+#[cfg(test)]
+impl Post {
+    /// A post named `name` with nothing but its title, created and modified at `date`, for
+    /// tests to override the fields they are about.
+    pub(crate) fn for_test(name: &str, date: DateTime<FixedOffset>) -> Self {
+        Post {
+            source: format!("{}.md", name).into(),
+            path: name.into(),
+            markdown: format!("# {}", name),
+            title: name.into(),
+            title_html: name.into(),
+            description: None,
+            modified: date,
+            created: date,
+            assets: vec![],
+            tags: vec![],
+            draft: false,
+            unlisted: false,
+            aliases: vec![],
+            series: None,
+            series_part: None,
+            warnings: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+impl Metadata {
+    /// Metadata with nothing but the path and dates of a post.
+    fn for_test(path: &str, date: DateTime<FixedOffset>) -> Self {
+        Metadata {
+            title: None,
+            description: None,
+            path: path.into(),
+            created: date,
+            modified: date,
+            timezone: None,
+            tags: vec![],
+            draft: false,
+            unlisted: false,
+            aliases: vec![],
+            series: None,
+            series_part: None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::offset::Local;
+
+    fn midnight(year: i32, month: u32, day: u32) -> DateTime<FixedOffset> {
+        from_local_datetime(NaiveDate::from_ymd(year, month, day).and_hms(0, 0, 0), None)
+    }
 
     /// Check that the title is extracted from the markdown, and that the content
     /// remains intact.
@@ -266,17 +823,43 @@ mod tests {
         let assets = vec![];
         let meta = Metadata {
             title: None,
+            description: None,
             path: "test".into(),
-            created: Local::today(),
-            modified: Local::today(),
+            created: in_time_zone(Local::now(), None),
+            modified: in_time_zone(Local::now(), None),
+            timezone: None,
+            tags: vec![],
+            draft: false,
+            unlisted: false,
+            aliases: vec![],
+            series: None,
+            series_part: None,
         };
 
-        let post = Post::from_sources(markdown.into(), assets, meta);
+        let post = Post::from_sources("test_post.md".into(), markdown.into(), assets, meta);
 
         assert_eq!(post.markdown, markdown);
         assert_eq!(post.title, "My header");
     }
 
+    /// Check that the whole title is extracted, keeping its formatting in the HTML version.
+    #[test]
+    fn rich_title_extracted() {
+        let post = Post::from_markdown(
+            "test_post.md",
+            "# Using `Vec` in *[Rust](https://rust-lang.org)*\n\nText.".into(),
+        );
+        assert_eq!(post.title, "Using Vec in Rust");
+        assert_eq!(post.title_html, "Using <code>Vec</code> in <em>Rust</em>");
+
+        let post = Post::from_markdown(
+            "test_post.md",
+            "```meta\ntitle: Fish & **chips**\n```\n# Ignored".into(),
+        );
+        assert_eq!(post.title, "Fish & chips");
+        assert_eq!(post.title_html, "Fish &amp; <strong>chips</strong>");
+    }
+
     /// Check that the meta block is extracted from the Post's markdown, and
     /// that properties can be overridden by it.
     #[test]
@@ -290,15 +873,23 @@ modified: 2020-05-05
 
 Some words.";
         let assets = vec![];
-        let date = TimeZone::ymd(&Local, 1999, 12, 01);
+        let date = midnight(1999, 12, 1);
         let meta = Metadata {
             title: None,
+            description: None,
             path: "test_post".into(),
-            created: date.clone(),
-            modified: date.clone(),
+            created: date,
+            modified: date,
+            timezone: None,
+            tags: vec![],
+            draft: false,
+            unlisted: false,
+            aliases: vec![],
+            series: None,
+            series_part: None,
         };
 
-        let post = Post::from_sources(content.into(), assets, meta);
+        let post = Post::from_sources("test_post.md".into(), content.into(), assets, meta);
 
         assert_eq!(
             post.markdown,
@@ -309,10 +900,84 @@ Some words.";
         );
         assert_eq!(post.title, "Overridden Title");
         assert_eq!(post.path, "custom_path");
-        assert_eq!(post.created, date.clone());
+        assert_eq!(post.created, date);
+
+        let date = midnight(2020, 5, 5);
+        assert_eq!(post.modified, date);
+    }
+
+    /// Check that YAML and TOML front matter is removed from the markdown and
+    /// overrides properties just like the meta block.
+    #[test]
+    fn front_matter_applied() {
+        let date = midnight(1999, 12, 1);
+        let meta = || Metadata::for_test("test_post", date);
+
+        let content = "---
+title: \"Overridden: Title\"
+path: custom_path
+published: 2020-02-20
+tags: [rust, web, rust]
+aliases: [/old/path/, older]
+series: Learning Rust
+series_part: 2
+---
+# My blog post
+
+Some words.";
+        let post = Post::from_sources("test_post.md".into(), content.into(), vec![], meta());
+        assert_eq!(post.markdown, "# My blog post\n\nSome words.");
+        assert_eq!(post.title, "Overridden: Title");
+        assert_eq!(post.path, "custom_path");
+        assert_eq!(post.tags, vec!["rust", "web"]);
+        assert_eq!(post.aliases, vec!["old/path", "older"]);
+        assert_eq!(post.series.as_deref(), Some("Learning Rust"));
+        assert_eq!(post.series_part, Some(2));
+        assert_eq!(post.created, midnight(2020, 2, 20));
+        assert_eq!(post.modified, date);
+
+        let content = "+++
+updated = 2020-05-05T10:00:00Z
++++
+# My blog post";
+        let post = Post::from_sources("test_post.md".into(), content.into(), vec![], meta());
+        assert_eq!(post.markdown, "# My blog post");
+        assert_eq!(post.title, "My blog post");
+        assert_eq!(
+            post.modified,
+            DateTime::parse_from_rfc3339("2020-05-05T10:00:00Z").unwrap()
+        );
+    }
+
+    /// Check that invalid front matter is still removed, but otherwise ignored.
+    #[test]
+    fn bad_front_matter_wont_crash() {
+        let content = "---\ntitle: [unclosed\n---\n# Title";
+        let meta = Metadata::for_test("test_post", in_time_zone(Local::now(), None));
+
+        let post = Post::from_sources("test_post.md".into(), content.into(), vec![], meta);
+        assert_eq!(post.markdown, "# Title");
+        assert_eq!(post.title, "Title");
+        assert_eq!(post.warnings.len(), 1);
+    }
 
-        let date = Local.ymd(2020, 05, 05);
-        assert_eq!(post.modified, date.clone());
+    /// Check that the tags of a post and whether it's a draft or unlisted are read from its
+    /// meta block or front matter.
+    #[test]
+    fn tags_and_visibility_applied() {
+        let meta = || Metadata::for_test("test_post", midnight(1999, 12, 1));
+
+        let content = "```meta\ntags: rust, web\nunlisted: true\n```\n# Title";
+        let post = Post::from_sources("test_post.md".into(), content.into(), vec![], meta());
+        assert_eq!(post.tags, vec!["rust", "web"]);
+        assert!(post.unlisted);
+        assert!(!post.draft);
+
+        let content = "---\ndraft: true\n---\n# Title";
+        let post = Post::from_sources("test_post.md".into(), content.into(), vec![], meta());
+        assert!(post.tags.is_empty());
+        assert!(post.draft);
+        assert!(!post.unlisted);
     }
 
     /// Check that an invalid meta block does not cause the program to panic.
@@ -324,15 +989,23 @@ title: Bad Meta
 
 :)";
         let assets = vec![];
-        let date = TimeZone::ymd(&Local, 1999, 12, 01);
+        let date = midnight(1999, 12, 1);
         let meta = Metadata {
             title: None,
+            description: None,
             path: "test_post".into(),
-            created: date.clone(),
-            modified: date.clone(),
+            created: date,
+            modified: date,
+            timezone: None,
+            tags: vec![],
+            draft: false,
+            unlisted: false,
+            aliases: vec![],
+            series: None,
+            series_part: None,
         };
 
-        let post = Post::from_sources(content.into(), assets, meta);
+        let post = Post::from_sources("test_post.md".into(), content.into(), assets, meta);
         assert_eq!(post.title, "Bad Meta");
     }
 
@@ -345,16 +1018,26 @@ title or not to title
 
 :D";
         let assets = vec![];
-        let date = TimeZone::ymd(&Local, 1999, 12, 01);
+        let date = midnight(1999, 12, 1);
         let meta = Metadata {
             title: None,
+            description: None,
             path: "test_post".into(),
-            created: date.clone(),
-            modified: date.clone(),
+            created: date,
+            modified: date,
+            timezone: None,
+            tags: vec![],
+            draft: false,
+            unlisted: false,
+            aliases: vec![],
+            series: None,
+            series_part: None,
         };
 
-        let post = Post::from_sources(content.into(), assets, meta);
+        let post = Post::from_sources("test_post.md".into(), content.into(), assets, meta);
         assert_eq!(post.markdown, ":D");
+        assert_eq!(post.warnings.len(), 1);
+        assert_eq!(post.warnings[0].line, 2);
     }
 
     /// Check that an invalid date does not cause the program to panic.
@@ -366,18 +1049,72 @@ created: today lol
 
 :-O";
         let assets = vec![];
-        let date = TimeZone::ymd(&Local, 1999, 12, 01);
+        let date = midnight(1999, 12, 1);
         let meta = Metadata {
             title: None,
+            description: None,
             path: "test_post".into(),
-            created: date.clone(),
-            modified: date.clone(),
+            created: date,
+            modified: date,
+            timezone: None,
+            tags: vec![],
+            draft: false,
+            unlisted: false,
+            aliases: vec![],
+            series: None,
+            series_part: None,
         };
 
-        let post = Post::from_sources(content.into(), assets, meta);
+        let post = Post::from_sources("test_post.md".into(), content.into(), assets, meta);
         assert_eq!(post.markdown, ":-O");
     }
 
+    /// Check that every problem in the metadata is reported with its location.
+    #[test]
+    fn meta_warnings_located() {
+        let meta = || Metadata::for_test("test_post", midnight(1999, 12, 1));
+
+        let content = "\n```meta\ntitle: Fine\npublihsed: 2020-02-20\n\ncreated: yesterday\n```\n";
+        let post = Post::from_sources("content/post.md".into(), content.into(), vec![], meta());
+        let warnings: Vec<_> = post.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "content/post.md:4: Unexpected meta key \"publihsed\"".to_string(),
+                format!(
+                    "content/post.md:6: Invalid value Text(\" yesterday\") for meta key \"created\" because {}",
+                    DATE_FORMAT_REASON
+                ),
+            ]
+        );
+        assert_eq!(post.created, midnight(1999, 12, 1));
+
+        let content = "---\ntitle: Fine\ndraft: maybe\ntags:\n---\n";
+        let post = Post::from_sources("post.md".into(), content.into(), vec![], meta());
+        let lines: Vec<_> = post.warnings.iter().map(|w| w.line).collect();
+        assert_eq!(lines, vec![3, 4]);
+    }
+
+    /// Check that dates keep their time and offset, and that the site's time zone is
+    /// used for those without an offset.
+    #[test]
+    fn dates_parsed_with_time_zones() {
+        let madrid = Some(Tz::Europe__Madrid);
+        let parsed = |date| parse_date(date, madrid).unwrap().to_rfc3339();
+
+        assert_eq!(parsed("2020-02-20"), "2020-02-20T00:00:00+01:00");
+        assert_eq!(parsed("2020-07-20 18:30"), "2020-07-20T18:30:00+02:00");
+        assert_eq!(parsed("2020-07-20T18:30:00"), "2020-07-20T18:30:00+02:00");
+        assert_eq!(parsed("2020-07-20T18:30:00Z"), "2020-07-20T18:30:00+00:00");
+        assert_eq!(
+            parsed("2020-07-20T18:30:00-0300"),
+            "2020-07-20T18:30:00-03:00"
+        );
+        // 02:30 does not exist when the clocks are moved forward.
+        assert_eq!(parsed("2020-03-29T02:30:00"), "2020-03-29T03:30:00+02:00");
+        assert!(parse_date("20/02/2020", madrid).is_err());
+    }
+
     /// Check that UTF-8 with BOM does not break meta parsing.
     #[test]
     fn utf8_bom_works_fine() {
@@ -386,21 +1123,27 @@ created: today lol
 ```
 
 # Boom"
-                .iter()
-                .copied()
-                .collect(),
+                .to_vec(),
         )
         .unwrap();
         let assets = vec![];
-        let date = TimeZone::ymd(&Local, 1999, 12, 01);
+        let date = midnight(1999, 12, 1);
         let meta = Metadata {
             title: None,
+            description: None,
             path: "test_post".into(),
-            created: date.clone(),
-            modified: date.clone(),
+            created: date,
+            modified: date,
+            timezone: None,
+            tags: vec![],
+            draft: false,
+            unlisted: false,
+            aliases: vec![],
+            series: None,
+            series_part: None,
         };
 
-        let post = Post::from_sources(content, assets, meta);
+        let post = Post::from_sources("test_post.md".into(), content, assets, meta);
         assert_eq!(post.title, "Boom");
     }
 
@@ -415,16 +1158,24 @@ println!("Jokes on you, this is not a summary but 🦀!");
 ```
 "#;
         let assets = vec![];
-        let date = TimeZone::ymd(&Local, 1999, 12, 01);
+        let date = midnight(1999, 12, 1);
         let meta = Metadata {
             title: None,
+            description: None,
             path: "test_post".into(),
-            created: date.clone(),
-            modified: date.clone(),
+            created: date,
+            modified: date,
+            timezone: None,
+            tags: vec![],
+            draft: false,
+            unlisted: false,
+            aliases: vec![],
+            series: None,
+            series_part: None,
         };
 
-        let post = Post::from_sources(content.into(), assets, meta);
-        assert_eq!(post.generate_summary(), None);
+        let post = Post::from_sources("test_post.md".into(), content.into(), assets, meta);
+        assert_eq!(post.generate_summary(200), None);
     }
 
     /// Check that the summary is found correctly.
@@ -442,19 +1193,86 @@ This totally summarizes the post.
 However, this does not.
 "#;
         let assets = vec![];
-        let date = TimeZone::ymd(&Local, 1999, 12, 01);
+        let date = midnight(1999, 12, 1);
         let meta = Metadata {
             title: None,
+            description: None,
             path: "test_post".into(),
-            created: date.clone(),
-            modified: date.clone(),
+            created: date,
+            modified: date,
+            timezone: None,
+            tags: vec![],
+            draft: false,
+            unlisted: false,
+            aliases: vec![],
+            series: None,
+            series_part: None,
         };
 
-        let post = Post::from_sources(content.into(), assets, meta);
+        let post = Post::from_sources("test_post.md".into(), content.into(), assets, meta);
 
         assert_eq!(
-            post.generate_summary(),
+            post.generate_summary(200),
             Some("This totally summarizes the post.".to_string())
         );
     }
+
+    /// Check that the summary contains the whole text of the first paragraph, up to the
+    /// given length.
+    #[test]
+    fn find_long_summary() {
+        let post = Post::from_markdown(
+            "test_post.md",
+            "# Title\n\n[A link](https://example.com) with *some*\n`code`, and more words.".into(),
+        );
+
+        assert_eq!(
+            post.generate_summary(200),
+            Some("A link with some code, and more words.".to_string())
+        );
+        assert_eq!(
+            post.generate_summary(25),
+            Some("A link with some code…".to_string())
+        );
+        assert_eq!(post.generate_summary(3), Some("A…".to_string()));
+
+        let post = Post::from_markdown("test_post.md", "abcdefghi é and more words".into());
+        assert_eq!(post.generate_summary(10), Some("abcdefghi…".to_string()));
+        assert_eq!(post.generate_summary(11), Some("abcdefghi é…".to_string()));
+    }
+
+    /// Check that the alternative text of images is left out of the summary.
+    #[test]
+    fn find_summary_without_images() {
+        let post = Post::from_markdown(
+            "test_post.md",
+            "# Title\n\n![cat](cat.png)\n\nA ![black cat](cat.png) cat.".into(),
+        );
+        assert_eq!(post.generate_summary(200), Some("A cat.".to_string()));
+    }
+
+    /// Check that the excerpt before the marker, or the description, are used as summary.
+    #[test]
+    fn find_excerpt_summary() {
+        let content = "# Title\n\nFirst paragraph.\n\nSecond *one*.\n\n<!-- more -->\n\nThe rest.";
+        let post = Post::from_markdown("test_post.md", content.into());
+        assert_eq!(
+            post.generate_summary(10),
+            Some("First paragraph. Second one.".to_string())
+        );
+
+        let post = Post::from_markdown(
+            "test_post.md",
+            format!("---\ndescription: All about it.\n---\n{}", content),
+        );
+        assert_eq!(post.generate_summary(10), Some("All about it.".to_string()));
+    }
+
+    #[test]
+    fn slugs_are_url_friendly() {
+        assert_eq!(slugify("Rust"), "rust");
+        assert_eq!(slugify("  Slow web / HTML!"), "slow-web-html");
+        assert_eq!(slugify("Año"), "año");
+        assert_eq!(slugify("?!"), "-");
+    }
 }
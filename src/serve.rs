@@ -0,0 +1,263 @@
+use crate::fs_action::FsAction;
+
+use std::fs::File;
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tiny_http::{Header, Method, Request, Response};
+
+/// Path under which the server answers live reload requests.
+pub const LIVE_RELOAD_PATH: &str = "/__pagong/live-reload";
+
+/// How long a live reload request is held open waiting for a rebuild.
+const LIVE_RELOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Script injected into every page, which waits for the server to report a new
+/// build and then reloads the page.
+const LIVE_RELOAD_SCRIPT: &str = r#"<script>
+(function () {
+    var build = null;
+    function poll() {
+        fetch("/__pagong/live-reload" + (build === null ? "" : "?since=" + build))
+            .then(function (response) { return response.text(); })
+            .then(function (latest) {
+                if (build !== null && latest !== build) {
+                    location.reload();
+                } else {
+                    build = latest;
+                    poll();
+                }
+            })
+            .catch(function () { setTimeout(poll, 1000); });
+    }
+    poll();
+})();
+</script>
+"#;
+
+/// Counter of finished builds, which live reload requests wait on.
+#[derive(Default)]
+struct BuildCounter {
+    count: Mutex<u64>,
+    changed: Condvar,
+}
+
+impl BuildCounter {
+    fn current(&self) -> u64 {
+        *self.count.lock().unwrap()
+    }
+
+    fn bump(&self) {
+        *self.count.lock().unwrap() += 1;
+        self.changed.notify_all();
+    }
+
+    /// Wait until the count differs from `since`, or the timeout elapses.
+    fn wait_change(&self, since: u64) -> u64 {
+        let count = self.count.lock().unwrap();
+        let (count, _) = self
+            .changed
+            .wait_timeout_while(count, LIVE_RELOAD_TIMEOUT, |count| *count == since)
+            .unwrap();
+        *count
+    }
+}
+
+/// Local HTTP server for previewing the generated site.
+pub struct Server {
+    builds: Arc<BuildCounter>,
+}
+
+impl Server {
+    /// Start serving the files in `root` at the given address from a background thread.
+    pub fn start(addr: SocketAddr, root: PathBuf) -> Result<Self> {
+        let server = tiny_http::Server::http(addr)
+            .map_err(|e| anyhow!("Could not start server at {}: {}", addr, e))?;
+        let builds = Arc::new(BuildCounter::default());
+
+        let thread_builds = Arc::clone(&builds);
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let builds = Arc::clone(&thread_builds);
+                let root = root.clone();
+                // Live reload requests block, so every request needs its own thread.
+                thread::spawn(move || {
+                    if let Err(e) = handle_request(request, &root, &builds) {
+                        log::debug!("Could not respond to request: {}", e);
+                    }
+                });
+            }
+        });
+
+        Ok(Self { builds })
+    }
+
+    /// Tell all open pages that the site has been rebuilt and they should reload.
+    pub fn reload(&self) {
+        self.builds.bump();
+    }
+}
+
+/// Add the live reload script to all the HTML pages that are going to be written.
+pub fn inject_live_reload(actions: &mut [FsAction]) {
+    for action in actions.iter_mut() {
+        if let FsAction::WriteFile { path, content } = action {
+            if path.extension().is_some_and(|ext| ext == "html") {
+                let at = content.rfind("</body>").unwrap_or(content.len());
+                content.insert_str(at, LIVE_RELOAD_SCRIPT);
+            }
+        }
+    }
+}
+
+/// Decode the `%XX` escapes in a URL path.
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = path.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Map the path of a URL to a file inside `root`, refusing to leave it.
+fn resolve_path(root: &Path, url_path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode(url_path)?;
+    let relative = Path::new(decoded.trim_start_matches('/'));
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    let path = root.join(relative);
+    if path.is_dir() {
+        Some(path.join("index.html"))
+    } else {
+        Some(path)
+    }
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    match ext.as_deref() {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("xml") => "application/atom+xml; charset=utf-8",
+        Some("txt") | Some("md") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("Header should be valid")
+}
+
+fn handle_request(request: Request, root: &Path, builds: &BuildCounter) -> Result<()> {
+    let url = request.url().to_string();
+    let (url_path, query) = match url.find('?') {
+        Some(i) => (&url[..i], &url[i + 1..]),
+        None => (url.as_str(), ""),
+    };
+
+    if *request.method() != Method::Get && *request.method() != Method::Head {
+        request.respond(Response::empty(405))?;
+        return Ok(());
+    }
+
+    if url_path == LIVE_RELOAD_PATH {
+        let since = query
+            .strip_prefix("since=")
+            .and_then(|since| since.parse().ok());
+        let count = match since {
+            Some(since) => builds.wait_change(since),
+            None => builds.current(),
+        };
+        request.respond(
+            Response::from_string(count.to_string())
+                .with_header(header("Content-Type", "text/plain"))
+                .with_header(header("Cache-Control", "no-store")),
+        )?;
+        return Ok(());
+    }
+
+    let file = resolve_path(root, url_path).and_then(|path| Some((File::open(&path).ok()?, path)));
+    match file {
+        Some((file, path)) => {
+            log::debug!("GET {}", url_path);
+            request.respond(
+                Response::from_file(file)
+                    .with_header(header("Content-Type", content_type(&path)))
+                    .with_header(header("Cache-Control", "no-store")),
+            )?;
+        }
+        None => {
+            log::debug!("GET {} (not found)", url_path);
+            request.respond(Response::from_string("404 Not Found").with_status_code(404))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_resolved_inside_root() {
+        let root = Path::new("/srv/dist");
+        assert_eq!(
+            resolve_path(root, "/my%20post/image.png"),
+            Some(PathBuf::from("/srv/dist/my post/image.png"))
+        );
+        assert_eq!(resolve_path(root, "/../secret"), None);
+        assert_eq!(resolve_path(root, "/a/%2E%2E/%2E%2E/secret"), None);
+        assert_eq!(resolve_path(root, "/bad%zz"), None);
+    }
+
+    #[test]
+    fn live_reload_injected_in_html_only() {
+        let mut actions = vec![
+            FsAction::WriteFile {
+                path: "dist/index.html".into(),
+                content: "<body>\n</body>\n</html>\n".into(),
+            },
+            FsAction::WriteFile {
+                path: "dist/atom.xml".into(),
+                content: "<feed></feed>".into(),
+            },
+        ];
+
+        inject_live_reload(&mut actions);
+
+        assert!(matches!(&actions[0],
+            FsAction::WriteFile { content, .. }
+                if content.contains(LIVE_RELOAD_PATH) && content.ends_with("</script>\n</body>\n</html>\n")
+        ));
+        assert!(matches!(&actions[1],
+            FsAction::WriteFile { content, .. } if content == "<feed></feed>"
+        ));
+    }
+}
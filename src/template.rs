@@ -0,0 +1,205 @@
+//! Layouts of the generated pages, which sites can replace with their own.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use minijinja::value::Value;
+use minijinja::{AutoEscape, Environment, Output, State};
+
+use crate::{Config, Post};
+
+/// The templates used unless the site overrides them, which produce the default pages.
+const BUILTIN_TEMPLATES: [(&str, &str); 7] = [
+    ("base.html", include_str!("../templates/base.html")),
+    ("post.html", include_str!("../templates/post.html")),
+    (
+        "post_list.html",
+        include_str!("../templates/post_list.html"),
+    ),
+    ("index.html", include_str!("../templates/index.html")),
+    ("tags.html", include_str!("../templates/tags.html")),
+    ("tag.html", include_str!("../templates/tag.html")),
+    ("series.html", include_str!("../templates/series.html")),
+];
+
+/// The templates the pages of a site are rendered with.
+#[derive(Debug)]
+pub struct Templates {
+    env: Environment<'static>,
+}
+
+/// Escape values like the rest of the generated HTML, rather than with the rules of the
+/// template engine, so that the built-in templates produce the same pages as before.
+fn escape_formatter(
+    out: &mut Output,
+    state: &State,
+    value: &Value,
+) -> Result<(), minijinja::Error> {
+    if state.auto_escape() != AutoEscape::Html
+        || value.is_safe()
+        || value.is_undefined()
+        || value.is_none()
+    {
+        return minijinja::escape_formatter(out, state, value);
+    }
+    let mut escaped = String::new();
+    crate::escape::escape_html(&mut escaped, &value.to_string())
+        .expect("Should not fail to escape HTML in-memory");
+    out.write_str(&escaped)?;
+    Ok(())
+}
+
+/// Filter to use a value inside an `href`, such as the `path` of a post.
+fn href_filter(value: &str) -> Value {
+    let mut escaped = String::new();
+    crate::escape::escape_href(&mut escaped, value)
+        .expect("Should not fail to escape HREF in-memory");
+    Value::from_safe_string(escaped)
+}
+
+impl Templates {
+    /// The built-in templates.
+    pub fn builtin() -> Self {
+        let mut env = Environment::new();
+        env.set_keep_trailing_newline(true);
+        env.set_formatter(escape_formatter);
+        env.add_filter("href", href_filter);
+        for (name, source) in BUILTIN_TEMPLATES.iter() {
+            env.add_template(name, source)
+                .expect("Built-in templates should be valid");
+        }
+        Self { env }
+    }
+
+    /// The built-in templates, replaced by the `.html` files with the same name in the given
+    /// directory. Performs I/O.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let mut templates = Self::builtin();
+        templates.add_dir(dir)?;
+        Ok(templates)
+    }
+
+    /// Add the `.html` files in the given directory, replacing the templates with the same
+    /// name. The rest are added too, so that templates can include them. Performs I/O.
+    pub fn add_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<()> {
+        let dir = dir.as_ref();
+        for child in
+            fs::read_dir(dir).context(format!("Could not read templates directory '{:?}'", dir))?
+        {
+            let path = child
+                .context(format!(
+                    "Could not list contents of templates directory '{:?}'",
+                    dir
+                ))?
+                .path();
+            if !path.is_file() || path.extension().is_none_or(|ext| ext != "html") {
+                continue;
+            }
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let source = fs::read_to_string(&path)
+                .context(format!("Could not read contents of template '{:?}'", path))?;
+            self.env
+                .add_template_owned(name, source)
+                .context(format!("Invalid template '{:?}'", path))?;
+        }
+        Ok(())
+    }
+
+    /// Render the template with the given name.
+    pub(crate) fn render(&self, name: &str, context: Value) -> Result<String> {
+        self.env
+            .get_template(name)
+            .and_then(|template| template.render(context))
+            .context(format!("Could not render template '{}'", name))
+    }
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+/// The fields of the site available to templates as `site`.
+pub(crate) fn site_value(config: &Config) -> Value {
+    Value::from_serialize(config)
+}
+
+/// The fields of a post available to templates, such as `post` or each of `posts`.
+pub(crate) fn post_value(config: &Config, post: &Post) -> Value {
+    minijinja::context! {
+        title => &post.title,
+        title_html => Value::from_safe_string(post.title_html.clone()),
+        description => &post.description,
+        summary => post.generate_summary(config.summary_length),
+        path => post.path.to_string_lossy(),
+        created => post.created.to_rfc3339(),
+        modified => post.modified.to_rfc3339(),
+        tags => &post.tags,
+        series => &post.series,
+        series_part => post.series_part,
+        draft => post.draft,
+        unlisted => post.unlisted,
+    }
+}
+
+/// The fields of several posts, in the same order.
+pub(crate) fn posts_value(config: &Config, posts: &[&Post]) -> Value {
+    posts
+        .iter()
+        .map(|post| post_value(config, post))
+        .collect::<Vec<_>>()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_escaped_like_the_rest_of_the_html() -> Result<()> {
+        let mut templates = Templates::builtin();
+        templates.env.add_template(
+            "test.html",
+            "{{ text }} <a href=\"{{ path | href }}\">{{ html }}</a>",
+        )?;
+        let html = templates.render(
+            "test.html",
+            minijinja::context! {
+                text => "a/b & <c>",
+                path => "a b/c",
+                html => Value::from_safe_string("<em>hi</em>".into()),
+            },
+        )?;
+        assert_eq!(
+            html,
+            "a/b &amp; &lt;c&gt; <a href=\"a%20b/c\"><em>hi</em></a>"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn templates_overridden_from_dir() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("pagong-templates-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("index.html"), "{% include \"extra.html\" %}")?;
+        fs::write(dir.join("extra.html"), "{{ site.title }}")?;
+        fs::write(dir.join("notes.txt"), "{{ ignored")?;
+
+        let templates = Templates::from_dir(&dir);
+        fs::remove_dir_all(&dir)?;
+        let templates = templates?;
+
+        let site = site_value(&Config::default());
+        let html = templates.render("index.html", minijinja::context! { site })?;
+        assert_eq!(html, Config::default().title);
+        assert!(templates
+            .render("tags.html", minijinja::context! { site => (), tags => () })
+            .is_ok());
+        Ok(())
+    }
+}
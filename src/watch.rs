@@ -0,0 +1,307 @@
+use crate::blog::PostChange;
+use crate::{Blog, CSS_FILE_NAME, FOLDER_POST_NAME, FOOTER_FILE_NAME, HEADER_FILE_NAME};
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// How long to wait for more changes after one is detected before reporting them.
+///
+/// Editors often perform several operations when saving a single file (such as writing
+/// to a temporary file and renaming it), so this avoids rebuilding more than once.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// What needs to be generated again after some files changed.
+#[derive(Debug, PartialEq)]
+pub enum Rebuild {
+    /// Nothing that is part of the generated site changed.
+    Nothing,
+    /// Only the given posts changed, so the rest of posts can be left as they are.
+    Posts(Vec<PostChange>),
+    /// Something that affects every page changed, or posts were added or removed.
+    Everything,
+}
+
+/// Watches the content directory, the configuration file and the theme for changes.
+pub struct ChangeWatcher {
+    // Kept alive so that events keep being delivered.
+    _watcher: RecommendedWatcher,
+    receiver: Receiver<notify::Result<notify::Event>>,
+    content_dir: PathBuf,
+    config_path: PathBuf,
+    theme_dir: Option<PathBuf>,
+}
+
+/// Make a path absolute, even if its last component does not exist (yet).
+fn canonicalize_lenient(path: &Path) -> Result<PathBuf> {
+    if let Ok(path) = path.canonicalize() {
+        return Ok(path);
+    }
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Path '{:?}' does not name a file", path))?;
+    Ok(parent
+        .canonicalize()
+        .context(format!("Could not resolve path '{:?}'", parent))?
+        .join(name))
+}
+
+impl ChangeWatcher {
+    pub fn new(content_dir: &Path, config_path: &Path, theme_dir: Option<&Path>) -> Result<Self> {
+        let content_dir = canonicalize_lenient(content_dir)?;
+        let config_path = canonicalize_lenient(config_path)?;
+        let theme_dir = theme_dir.map(canonicalize_lenient).transpose()?;
+
+        let (sender, receiver) = channel();
+        let mut watcher =
+            notify::recommended_watcher(sender).context("Could not create file watcher")?;
+        watcher
+            .watch(&content_dir, RecursiveMode::Recursive)
+            .context(format!("Could not watch directory '{:?}'", content_dir))?;
+
+        // Watch the directory containing the configuration file and not the file itself,
+        // so that it can be created later and editors that replace the file don't break it.
+        if let Some(config_dir) = config_path.parent() {
+            if !config_dir.starts_with(&content_dir) {
+                watcher
+                    .watch(config_dir, RecursiveMode::NonRecursive)
+                    .context(format!("Could not watch directory '{:?}'", config_dir))?;
+            }
+        }
+
+        if let Some(theme_dir) = &theme_dir {
+            watcher
+                .watch(theme_dir, RecursiveMode::Recursive)
+                .context(format!("Could not watch directory '{:?}'", theme_dir))?;
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            receiver,
+            content_dir,
+            config_path,
+            theme_dir,
+        })
+    }
+
+    /// Whether a change to the given path is relevant to the generated site.
+    fn is_relevant(&self, path: &Path) -> bool {
+        path.starts_with(&self.content_dir)
+            || path == self.config_path
+            || self
+                .theme_dir
+                .as_ref()
+                .is_some_and(|theme_dir| path.starts_with(theme_dir))
+    }
+
+    /// Block until some relevant file changes, and return the paths that changed.
+    pub fn wait(&self) -> Result<Vec<PathBuf>> {
+        let mut changed = Vec::new();
+        loop {
+            let event = if changed.is_empty() {
+                self.receiver
+                    .recv()
+                    .map_err(|_| anyhow!("File watcher stopped unexpectedly"))?
+            } else {
+                match self.receiver.recv_timeout(DEBOUNCE) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        return Err(anyhow!("File watcher stopped unexpectedly"))
+                    }
+                }
+            };
+
+            let event = event.context("Could not watch for file changes")?;
+            if let EventKind::Access(_) = event.kind {
+                continue;
+            }
+            for path in event.paths {
+                if self.is_relevant(&path) && !changed.contains(&path) {
+                    changed.push(path);
+                }
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// Figure out what needs to be generated again after the given paths changed.
+    pub fn classify(&self, blog: &Blog, changed: &[PathBuf]) -> Rebuild {
+        classify(
+            &self.content_dir,
+            &self.config_path,
+            self.theme_dir.as_deref(),
+            blog,
+            changed,
+        )
+    }
+}
+
+fn classify(
+    content_dir: &Path,
+    config_path: &Path,
+    theme_dir: Option<&Path>,
+    blog: &Blog,
+    changed: &[PathBuf],
+) -> Rebuild {
+    let mut changes: Vec<PostChange> = Vec::new();
+
+    for path in changed {
+        // The theme affects every page.
+        if path == config_path || theme_dir.is_some_and(|theme_dir| path.starts_with(theme_dir)) {
+            return Rebuild::Everything;
+        }
+        let relative = match path.strip_prefix(content_dir) {
+            Ok(relative) => relative,
+            Err(_) => continue,
+        };
+        let mut components = relative.components();
+        let entry = match components.next() {
+            Some(Component::Normal(entry)) => entry,
+            _ => return Rebuild::Everything,
+        };
+        let inside_entry: PathBuf = components.collect();
+
+        if inside_entry.as_os_str().is_empty()
+            && [HEADER_FILE_NAME, CSS_FILE_NAME, FOOTER_FILE_NAME]
+                .iter()
+                .any(|&name| entry == name)
+        {
+            return Rebuild::Everything;
+        }
+
+        let post = blog
+            .posts
+            .iter()
+            .find(|post| post.source.file_name() == Some(entry));
+        let post = match post {
+            Some(post) if post.source.exists() => post,
+            // A post was removed, so it must disappear from everywhere.
+            Some(_) => return Rebuild::Everything,
+            None => {
+                let is_new_post = inside_entry.as_os_str().is_empty()
+                    && (path.is_dir() || path.extension().is_some_and(|ext| ext == "md"));
+                if is_new_post || !inside_entry.as_os_str().is_empty() {
+                    return Rebuild::Everything;
+                }
+                // Some other file which is not part of the site (such as an editor's swap file).
+                continue;
+            }
+        };
+
+        let index = match changes.iter().position(|c| c.source == post.source) {
+            Some(index) => index,
+            None => {
+                changes.push(PostChange {
+                    source: post.source.clone(),
+                    assets: Vec::new(),
+                });
+                changes.len() - 1
+            }
+        };
+
+        // Anything inside a post's directory other than its text is an asset.
+        if !inside_entry.as_os_str().is_empty() && inside_entry != Path::new(FOLDER_POST_NAME) {
+            let asset = post.source.join(&inside_entry);
+            if !changes[index].assets.contains(&asset) {
+                changes[index].assets.push(asset);
+            }
+        }
+    }
+
+    if changes.is_empty() {
+        Rebuild::Nothing
+    } else {
+        Rebuild::Posts(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Post};
+    use chrono::offset::Local;
+
+    fn post(source: &Path) -> Post {
+        Post {
+            source: source.into(),
+            ..Post::for_test("test_post", Local::now().into())
+        }
+    }
+
+    #[test]
+    fn changes_classified() -> std::io::Result<()> {
+        let content_dir = std::env::temp_dir().join(format!("pagong-watch-{}", std::process::id()));
+        let config_path = content_dir.join("pagong.toml");
+        // The posts must exist, because removed posts are detected.
+        let dir_post = content_dir.join("folder-post");
+        let file_post = content_dir.join("file-post.md");
+        std::fs::create_dir_all(&dir_post)?;
+        std::fs::write(&file_post, "# File post")?;
+        let blog = Blog::new(Config::default(), vec![post(&dir_post), post(&file_post)]);
+        let theme_dir = Path::new("/blog/theme");
+        let classify = |changed: Vec<PathBuf>| {
+            classify(&content_dir, &config_path, Some(theme_dir), &blog, &changed)
+        };
+
+        let file_post_changed = classify(vec![file_post.clone()]);
+        let dir_post_changed = classify(vec![
+            dir_post.join(FOLDER_POST_NAME),
+            dir_post.join("image.png"),
+            dir_post.join("image.png"),
+        ]);
+        std::fs::remove_dir_all(&content_dir)?;
+        let file_post_removed = classify(vec![file_post.clone()]);
+
+        assert_eq!(classify(vec![config_path.clone()]), Rebuild::Everything);
+        assert_eq!(
+            classify(vec![content_dir.join("style.css")]),
+            Rebuild::Everything
+        );
+        assert_eq!(
+            classify(vec![theme_dir.join("templates/post.html")]),
+            Rebuild::Everything
+        );
+        assert_eq!(
+            classify(vec![content_dir.join("new.md")]),
+            Rebuild::Everything
+        );
+        assert_eq!(
+            classify(vec![content_dir.join("removed")]),
+            Rebuild::Nothing
+        );
+        assert_eq!(
+            classify(vec![content_dir.join(".file-post.md.swp")]),
+            Rebuild::Nothing
+        );
+        assert_eq!(
+            classify(vec![Path::new("/elsewhere").into()]),
+            Rebuild::Nothing
+        );
+
+        assert_eq!(
+            file_post_changed,
+            Rebuild::Posts(vec![PostChange {
+                source: file_post,
+                assets: vec![],
+            }])
+        );
+        assert_eq!(
+            dir_post_changed,
+            Rebuild::Posts(vec![PostChange {
+                source: dir_post.clone(),
+                assets: vec![dir_post.join("image.png")],
+            }])
+        );
+        assert_eq!(file_post_removed, Rebuild::Everything);
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+//! Weight of the generated pages, which is everything readers download to see each of them.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::markup::{self, percent_decode};
+use crate::FsAction;
+
+/// Values of the `rel` attribute of `<link>` whose files are downloaded along with the page.
+const LOADED_LINK_RELS: [&str; 3] = ["stylesheet", "icon", "preload"];
+
+/// The size of a generated page, along with the files it makes browsers download.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageWeight {
+    /// Path of the page's HTML file.
+    pub path: PathBuf,
+    /// Size of the HTML, in bytes.
+    pub html: u64,
+    /// Path and size in bytes of every file loaded by the page (such as stylesheets and
+    /// images), including those loaded by its stylesheets (such as fonts).
+    pub resources: Vec<(PathBuf, u64)>,
+}
+
+impl PageWeight {
+    /// Total amount of bytes needed to load the page.
+    pub fn total(&self) -> u64 {
+        self.html + self.resources.iter().map(|(_, size)| size).sum::<u64>()
+    }
+
+    /// Add the resource at the given path, unless it was already added or the actions don't
+    /// write it. Returns whether it was added.
+    fn add_resource(&mut self, output: &Output, path: PathBuf) -> bool {
+        if self.resources.iter().any(|(added, _)| *added == path) {
+            return false;
+        }
+        match output.get(path.as_path()).and_then(Written::size) {
+            Some(size) => {
+                self.resources.push((path, size));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A file that the actions write to the output directory.
+enum Written<'a> {
+    /// A file written with the given content.
+    Content(&'a str),
+    /// A copy of the given file, or an optimized image which is never bigger than it.
+    File(&'a Path),
+}
+
+impl Written<'_> {
+    /// Size of the file, in bytes, if it can be known before the actions are executed.
+    fn size(&self) -> Option<u64> {
+        match self {
+            Written::Content(content) => Some(content.len() as u64),
+            Written::File(source) => fs::metadata(source).ok().map(|metadata| metadata.len()),
+        }
+    }
+
+    /// Contents of the file, if it's text.
+    fn text(&self) -> Option<Cow<'_, str>> {
+        match self {
+            Written::Content(content) => Some(Cow::Borrowed(content)),
+            Written::File(source) => fs::read_to_string(source).ok().map(Cow::Owned),
+        }
+    }
+}
+
+/// The files written by the actions, by their path.
+type Output<'a> = HashMap<&'a Path, Written<'a>>;
+
+/// Measure the weight of every HTML page written by the actions, before they are executed,
+/// with `root` being the directory the site is generated in. Performs I/O, but only to read
+/// the sources of the files that are copied.
+///
+/// Only the files written by the actions are taken into account, leaving out those outside the
+/// site (such as those with absolute URLs). Optimized images are counted with the size of
+/// their original, which they are never bigger than, and compressed copies are left out, so
+/// the weight is that of the uncompressed files. The pages are sorted from heaviest to
+/// lightest.
+pub fn page_weights<P: AsRef<Path>>(root: P, actions: &[FsAction]) -> Vec<PageWeight> {
+    let root = root.as_ref();
+    let mut output = Output::new();
+    for action in actions {
+        match action {
+            FsAction::WriteFile { path, content } => {
+                output.insert(path, Written::Content(content));
+            }
+            FsAction::Copy { source, dest } | FsAction::OptimizeImage { source, dest, .. } => {
+                output.insert(dest, Written::File(source));
+            }
+            _ => {}
+        }
+    }
+
+    let mut weights: Vec<_> = actions
+        .iter()
+        .filter_map(|action| match action {
+            FsAction::WriteFile { path, content }
+                if path.extension().is_some_and(|ext| ext == "html") =>
+            {
+                Some(page_weight(&output, root, path, content))
+            }
+            _ => None,
+        })
+        .collect();
+    weights.sort_by(|a, b| b.total().cmp(&a.total()).then_with(|| a.path.cmp(&b.path)));
+    weights
+}
+
+/// Measure the weight of the page at the given path, with the given HTML.
+fn page_weight(output: &Output, root: &Path, path: &Path, html: &str) -> PageWeight {
+    let mut weight = PageWeight {
+        path: path.to_path_buf(),
+        html: html.len() as u64,
+        resources: Vec::new(),
+    };
+    let page_dir = path.parent().unwrap_or(root);
+
+    for tag in markup::tags(html) {
+        let rels: Vec<_> = tag
+            .attribute("rel")
+            .unwrap_or("")
+            .split_whitespace()
+            .collect();
+        let is_stylesheet = tag.name == "link" && rels.contains(&"stylesheet");
+        let reference = match tag.name.as_ref() {
+            "link" if rels.iter().any(|rel| LOADED_LINK_RELS.contains(rel)) => {
+                tag.attribute("href")
+            }
+            "video" => tag.attribute("poster").or_else(|| tag.attribute("src")),
+            _ => tag.attribute("src"),
+        };
+
+        if let Some(resource) = reference.and_then(|r| resolve(root, page_dir, r)) {
+            if weight.add_resource(output, resource.clone()) && is_stylesheet {
+                if let Some(css) = output.get(resource.as_path()).and_then(Written::text) {
+                    let css_dir = resource.parent().unwrap_or(root);
+                    add_css_resources(&mut weight, output, root, css_dir, &css);
+                }
+            }
+        }
+    }
+
+    // The contents of inlined stylesheets are relative to the page itself.
+    let mut rest = html;
+    while let Some(start) = rest.find("<style") {
+        rest = &rest[start..];
+        let end = rest.find("</style>").unwrap_or(rest.len());
+        add_css_resources(&mut weight, output, root, page_dir, &rest[..end]);
+        rest = &rest[end..];
+    }
+
+    weight
+}
+
+/// Add the files referenced with `url()` by the stylesheet, which is in `css_dir`.
+fn add_css_resources(
+    weight: &mut PageWeight,
+    output: &Output,
+    root: &Path,
+    css_dir: &Path,
+    css: &str,
+) {
+    let mut rest = css;
+    while let Some(start) = rest.find("url(") {
+        rest = &rest[start + 4..];
+        let end = rest.find(')').unwrap_or(rest.len());
+        let reference = rest[..end].trim().trim_matches(['"', '\'']);
+        if let Some(resource) = resolve(root, css_dir, reference) {
+            weight.add_resource(output, resource);
+        }
+        rest = &rest[end..];
+    }
+}
+
+/// The path of the file a reference inside the site points to, relative to `dir`, or to `root`
+/// if it starts with a slash. References to other sites are ignored.
+fn resolve(root: &Path, dir: &Path, reference: &str) -> Option<PathBuf> {
+    let reference = reference.split(['#', '?']).next().unwrap_or("");
+    let has_scheme = reference
+        .find(':')
+        .is_some_and(|colon| !reference[..colon].contains('/'));
+    if reference.is_empty() || has_scheme || reference.starts_with("//") {
+        return None;
+    }
+
+    let reference = percent_decode(reference);
+    let (base, relative) = match reference.strip_prefix('/') {
+        Some(relative) => (root, relative),
+        None => (dir, reference.as_str()),
+    };
+    let mut path = base.to_path_buf();
+    for component in Path::new(relative).components() {
+        match component {
+            Component::ParentDir => {
+                path.pop();
+            }
+            Component::Normal(part) => path.push(part),
+            _ => {}
+        }
+    }
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn references_resolved() {
+        let root = Path::new("dist");
+        let dir = Path::new("dist/post");
+        let resolve = |reference| resolve(root, dir, reference);
+
+        assert_eq!(resolve("a%20b.png"), Some("dist/post/a b.png".into()));
+        assert_eq!(
+            resolve("../css/style.css"),
+            Some("dist/css/style.css".into())
+        );
+        assert_eq!(resolve("./img.png?v=2#x"), Some("dist/post/img.png".into()));
+        assert_eq!(
+            resolve("/static/font.woff2"),
+            Some("dist/static/font.woff2".into())
+        );
+        assert_eq!(resolve("https://example.com/a.png"), None);
+        assert_eq!(resolve("//example.com/a.png"), None);
+        assert_eq!(resolve("data:image/png;base64,AAAA"), None);
+        assert_eq!(resolve("#top"), None);
+    }
+
+    #[test]
+    fn page_weight_includes_resources() -> anyhow::Result<()> {
+        // Only the sources of the copied files exist, since nothing is written yet.
+        let source = std::env::temp_dir().join(format!("pagong-weight-{}", std::process::id()));
+        fs::create_dir_all(&source)?;
+        fs::write(source.join("f.woff2"), "0123456789")?;
+        fs::write(source.join("cat.jpg"), "12345")?;
+
+        let root = Path::new("dist");
+        let page = "<link rel=\"stylesheet\" href=\"../css/style.css\">\
+                    <link rel=\"alternate\" href=\"../atom.xml\">\
+                    <img src=\"cat.jpg\"><img src=\"cat.jpg\"><img src=\"missing.png\">\
+                    <a href=\"../index.html\">Home</a>";
+        let small = "<style>body{background:url(cat.jpg)}</style>";
+        let actions = vec![
+            FsAction::WriteFile {
+                path: root.join("css/style.css"),
+                content: "@font-face{src:url(\"../static/f.woff2\")}".into(),
+            },
+            FsAction::Copy {
+                source: source.join("f.woff2"),
+                dest: root.join("static/f.woff2"),
+            },
+            FsAction::OptimizeImage {
+                source: source.join("cat.jpg"),
+                dest: root.join("post/cat.jpg"),
+                max_width: 100,
+                quality: 80,
+                webp: false,
+            },
+            FsAction::Compress {
+                source: root.join("css/style.css"),
+                dest: root.join("css/style.css.gz"),
+                encoding: crate::Encoding::Gzip,
+            },
+            FsAction::WriteFile {
+                path: root.join("post/index.html"),
+                content: page.into(),
+            },
+            FsAction::WriteFile {
+                path: root.join("post/small.html"),
+                content: small.into(),
+            },
+            FsAction::WriteFile {
+                path: root.join("atom.xml"),
+                content: "<feed/>".into(),
+            },
+        ];
+        let weights = page_weights(root, &actions);
+        fs::remove_dir_all(&source)?;
+
+        assert_eq!(
+            weights,
+            [
+                PageWeight {
+                    path: root.join("post/index.html"),
+                    html: page.len() as u64,
+                    resources: vec![
+                        (root.join("css/style.css"), 40),
+                        (root.join("static/f.woff2"), 10),
+                        (root.join("post/cat.jpg"), 5),
+                    ],
+                },
+                PageWeight {
+                    path: root.join("post/small.html"),
+                    html: small.len() as u64,
+                    resources: vec![(root.join("post/cat.jpg"), 5)],
+                },
+            ]
+        );
+        assert_eq!(weights[1].total(), small.len() as u64 + 5);
+        Ok(())
+    }
+}